@@ -1,36 +1,42 @@
-use ir_parser;
-use std::fs::File;
 use std::path::Path;
 
+// the directory in here with a story.xml is the base language,
+// and the ones with a strings.xml are translations of it
+const DIALOGUE_SRC: &str = "../dialogue-src";
+// the game loads them from here at runtime, as dialogue/<lang>/story.bincode or strings.bincode
+const DIALOGUE_OUT: &str = "dialogue";
+
 fn main() {
-    detect_changes(Path::new("../dialogue-src"));
+    detect_changes(Path::new(DIALOGUE_SRC));
+
+    // source maps are only for the debug overlay, and pseudo-localization is only for testing
+    let debug = std::env::var("PROFILE").map_or(true, |p| p != "release");
+    let options = ir_parser::CompileOptions {
+        source_maps: debug,
+        pseudo: debug,
+    };
 
-    // languages
-    let path = Path::new("../dialogue-src/en");
-    let in_file = File::open("../dialogue-src/en/intro.xml").unwrap();
-    let out_path = Path::new("../dialogue-src/en/ir");
-    ir_parser::compile_ir(path, in_file, out_path);
+    let src = Path::new(DIALOGUE_SRC);
+    if let Err(errors) = ir_parser::compile_all(src, Path::new(DIALOGUE_OUT), &options) {
+        for error in &errors {
+            eprintln!("{}\n", error);
+        }
+        // cargo shows our stderr when the build script fails
+        std::process::exit(1);
+    }
 }
 
+// directories are watched too, so adding a chapter or a language reruns the build
 fn detect_changes(path: &Path) {
-    for dir in std::fs::read_dir(path).unwrap() {
-        let p = dir.unwrap().path();
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    for entry in std::fs::read_dir(path).unwrap() {
+        let p = entry.unwrap().path();
 
         if p.is_dir() {
             detect_changes(&p);
         } else {
-            print!("cargo:rerun-if-changed={}", p.clone().to_str().unwrap());
+            println!("cargo:rerun-if-changed={}", p.display());
         }
     }
-
-    // let walk = WalkDir::new(path);
-    //
-    // for p in walk.into_iter().filter_map(Result::ok) {
-    //     if p.file_type().is_dir() {
-    //         detect_changes(p.path());
-    //     } else {
-    //         print!("cargo:rerun-if-changed={}", path.clone().to_str().unwrap());
-    //         // println!("{}", path.clone().to_str().unwrap());
-    //     }
-    // }
 }
@@ -0,0 +1,221 @@
+use crate::dialogue::world::World;
+use ir::ast::{CommandHandler, Vocab};
+
+// what a typed command turned out to mean
+#[derive(Debug, PartialEq)]
+pub enum Parsed {
+    // continue from this handler's target
+    Matched(usize),
+    // it could be any of these nouns, so the player has to say which, then continue from its target
+    Ambiguous(Vec<(String, usize)>),
+    NotUnderstood,
+}
+
+// they don't change what anyone means, "take the key" is "take key"
+const ARTICLES: [&str; 3] = ["the", "a", "an"];
+
+pub fn parse(input: &str, vocab: &Vocab, handlers: &[CommandHandler], world: &World) -> Parsed {
+    let words: Vec<String> = input
+        .split_whitespace()
+        .map(|w| {
+            w.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|w| !w.is_empty() && !ARTICLES.contains(&w.as_str()))
+        .collect();
+
+    // the verb takes as many words as it can, so "pick up key" is "pick up" and not "pick"
+    let verb = vocab
+        .verbs
+        .iter()
+        .flat_map(|(verb, synonyms)| {
+            std::iter::once(verb)
+                .chain(synonyms)
+                .map(move |said| (verb, said))
+        })
+        .filter(|(_, said)| starts_with(&words, said))
+        .max_by_key(|(_, said)| said.split(' ').count());
+    let (verb, said) = match verb {
+        Some(verb) => verb,
+        None => return Parsed::NotUnderstood,
+    };
+    let rest = &words[said.split(' ').count()..];
+    let handlers = handlers.iter().filter(|h| h.verb == *verb);
+
+    if rest.is_empty() {
+        return match handlers.clone().find(|h| h.noun.is_none()) {
+            Some(handler) => Parsed::Matched(handler.target),
+            None => Parsed::NotUnderstood,
+        };
+    }
+
+    // only nouns the verb does something with count, so if there's one key
+    // you can take, "take key" means that one
+    let mut candidates: Vec<(&String, usize)> = vec![];
+    for handler in handlers {
+        if let Some(noun) = &handler.noun {
+            let names = names(vocab, noun);
+            let new = !candidates.iter().any(|(other, _)| *other == noun);
+            if new && names.iter().any(|name| contains_all(name, rest)) {
+                candidates.push((noun, handler.target));
+            }
+        }
+    }
+
+    // items that aren't around are probably not what they meant, unless nothing is
+    if candidates.iter().any(|(noun, _)| world.is_present(noun)) {
+        candidates.retain(|(noun, _)| !world.is_item(noun) || world.is_present(noun));
+    }
+
+    // saying all of a noun's name means that one, even if its words are in others' names too
+    let said = rest.join(" ");
+    let exact: Vec<_> = candidates
+        .iter()
+        .filter(|(noun, _)| names(vocab, noun).contains(&said.as_str()))
+        .collect();
+    if let [(_, target)] = exact.as_slice() {
+        return Parsed::Matched(*target);
+    }
+
+    match candidates.as_slice() {
+        [] => Parsed::NotUnderstood,
+        [(_, target)] => Parsed::Matched(*target),
+        _ => Parsed::Ambiguous(
+            candidates
+                .into_iter()
+                .map(|(noun, target)| (noun.clone(), target))
+                .collect(),
+        ),
+    }
+}
+
+// a noun's name, and its synonyms
+fn names<'a>(vocab: &'a Vocab, noun: &'a str) -> Vec<&'a str> {
+    let synonyms = vocab.nouns.get(noun).map_or(&[][..], Vec::as_slice);
+    std::iter::once(noun)
+        .chain(synonyms.iter().map(String::as_str))
+        .collect()
+}
+
+fn starts_with(words: &[String], phrase: &str) -> bool {
+    let phrase: Vec<&str> = phrase.split(' ').collect();
+    words.len() >= phrase.len() && words.iter().zip(&phrase).all(|(w, p)| w == p)
+}
+
+fn contains_all(name: &str, words: &[String]) -> bool {
+    let name: Vec<&str> = name.split(' ').collect();
+    words.iter().all(|w| name.contains(&w.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Parsed};
+    use crate::dialogue::world::World;
+    use ir::ast::{CommandHandler, Item, Room, Vocab};
+
+    fn vocab() -> Vocab {
+        let words = |words: &[(&str, &[&str])]| {
+            words
+                .iter()
+                .map(|(word, synonyms)| {
+                    let synonyms = synonyms.iter().map(|s| s.to_string()).collect();
+                    (word.to_string(), synonyms)
+                })
+                .collect()
+        };
+        Vocab {
+            verbs: words(&[("take", &["get", "pick up"]), ("look", &[]), ("pick", &[])]),
+            nouns: words(&[("brass key", &[]), ("iron key", &[]), ("lamp", &["light"])]),
+        }
+    }
+
+    fn handlers() -> Vec<CommandHandler> {
+        let handler = |verb: &str, noun: Option<&str>, target| CommandHandler {
+            verb: verb.to_string(),
+            noun: noun.map(str::to_string),
+            target,
+        };
+        vec![
+            handler("take", Some("brass key"), 1),
+            handler("take", Some("iron key"), 2),
+            handler("take", Some("lamp"), 3),
+            handler("look", None, 4),
+            handler("pick", Some("lamp"), 5),
+        ]
+    }
+
+    // the brass key's here, the lamp's in the inventory, and the iron key is wherever
+    fn world(iron_key: &str) -> World {
+        let mut world = ir::ast::World {
+            start: "cell".to_string(),
+            ..Default::default()
+        };
+        for room in &["cell", "hallway"] {
+            world.rooms.insert(room.to_string(), Room::default());
+        }
+        for (id, location) in &[
+            ("brass key", "cell"),
+            ("iron key", iron_key),
+            ("lamp", "inventory"),
+        ] {
+            let item = Item {
+                location: location.to_string(),
+                state: String::new(),
+            };
+            world.items.insert(id.to_string(), item);
+        }
+        World::new(&world)
+    }
+
+    fn run(input: &str, world: &World) -> Parsed {
+        parse(input, &vocab(), &handlers(), world)
+    }
+
+    #[test]
+    fn verbs() {
+        let world = world("cell");
+        assert_eq!(run("take lamp", &world), Parsed::Matched(3));
+        assert_eq!(run("get lamp", &world), Parsed::Matched(3));
+        assert_eq!(run("  LOOK! ", &world), Parsed::Matched(4));
+        // "pick up" is a synonym of take, even though "pick" is a verb too
+        assert_eq!(run("pick up lamp", &world), Parsed::Matched(3));
+        assert_eq!(run("pick lamp", &world), Parsed::Matched(5));
+        assert_eq!(run("dance", &world), Parsed::NotUnderstood);
+        assert_eq!(run("", &world), Parsed::NotUnderstood);
+    }
+
+    #[test]
+    fn nouns() {
+        let world = world("cell");
+        assert_eq!(run("take light", &world), Parsed::Matched(3));
+        assert_eq!(run("take the lamp", &world), Parsed::Matched(3));
+        assert_eq!(run("take a brass key", &world), Parsed::Matched(1));
+        assert_eq!(run("take brass", &world), Parsed::Matched(1));
+        assert_eq!(run("take lamp key", &world), Parsed::NotUnderstood);
+        // take needs a noun, and look doesn't have one
+        assert_eq!(run("take", &world), Parsed::NotUnderstood);
+        assert_eq!(run("look lamp", &world), Parsed::NotUnderstood);
+    }
+
+    #[test]
+    fn ambiguous() {
+        let world = world("cell");
+        assert_eq!(
+            run("take key", &world),
+            Parsed::Ambiguous(vec![
+                ("brass key".to_string(), 1),
+                ("iron key".to_string(), 2)
+            ])
+        );
+        assert_eq!(run("take the iron key", &world), Parsed::Matched(2));
+    }
+
+    #[test]
+    fn present_items_first() {
+        // the only key around is the brass one
+        let world = world("hallway");
+        assert_eq!(run("take key", &world), Parsed::Matched(1));
+        // but one that isn't around can still be named
+        assert_eq!(run("take iron key", &world), Parsed::Matched(2));
+    }
+}
@@ -0,0 +1,64 @@
+use ir::ast::Effect;
+
+// where a glyph should be drawn relative to its resting place, and in what colour.
+// `idx` is the glyph's position in its span and `age` is how many ticks ago it was typed.
+pub fn apply(
+    effects: &[Effect],
+    ticks: u64,
+    idx: usize,
+    age: u64,
+    color: [f32; 4],
+) -> ((f32, f32), [f32; 4]) {
+    let t = ticks as f32;
+    let i = idx as f32;
+    let mut offset = (0.0, 0.0);
+    let mut color = color;
+
+    for effect in effects {
+        match effect {
+            Effect::Wiggle => {
+                offset.0 += (t * 0.3 + i * 1.7).sin() * 0.75;
+                offset.1 += (t * 0.4 + i * 2.3).cos() * 0.75;
+            }
+            Effect::Wave => {
+                offset.1 += (t * 0.15 + i * 0.6).sin() * 2.0;
+            }
+            Effect::Shake => {
+                // a new random-ish offset every couple of ticks
+                let n = hash(ticks / 2, idx);
+                offset.0 += ((n & 0xff) as f32 / 255.0 - 0.5) * 2.0;
+                offset.1 += (((n >> 8) & 0xff) as f32 / 255.0 - 0.5) * 2.0;
+            }
+            Effect::Rainbow => {
+                let [r, g, b] = hue((t * 0.01 + i * 0.08).fract());
+                color = [r, g, b, color[3]];
+            }
+            Effect::FadeIn => {
+                color[3] *= (age as f32 / 20.0).min(1.0);
+            }
+        }
+    }
+
+    (offset, color)
+}
+
+fn hash(a: u64, b: usize) -> u64 {
+    let mut x =
+        a.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ (b as u64).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x ^= x >> 31;
+    x.wrapping_mul(0x94d0_49bb_1331_11eb)
+}
+
+// a fully saturated colour, h in 0..1
+fn hue(h: f32) -> [f32; 3] {
+    let h = h * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    match h as u32 {
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x],
+    }
+}
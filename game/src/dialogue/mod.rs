@@ -1,11 +1,25 @@
+mod command;
+mod effects;
+mod random;
+mod vars;
+mod voice;
+mod world;
+
+use crate::dialogue::command::Parsed;
+use crate::dialogue::random::Random;
+use crate::dialogue::vars::Variables;
+use crate::dialogue::voice::VoiceStyle;
+use crate::dialogue::world::World;
 use crate::dialogue::DialogueSpan::Text;
-use crate::graphics::draw::DrawCommand::DrawString;
+use crate::graphics::draw::DrawCommand::{DrawChar, DrawString};
 use crate::graphics::text::BasicText;
+use crate::resources;
 use crate::systems::audio::AudioSysMsg;
 use crate::systems::game::IO;
 use ir::ast;
 use ir::ast::LineChild::Span;
-use ir::ast::{Action, ChExpr, Instruction, LineChild, TextProperties};
+use ir::ast::{Action, ChExpr, Instruction, LineChild, TextProperties, Value};
+use serde::{Deserialize, Serialize};
 use std::vec::IntoIter;
 
 #[derive(Debug)]
@@ -51,37 +65,166 @@ impl LineBuffer {
 #[derive(Debug, Clone)]
 enum DialogueSpan {
     Text(BasicText),
+    Animated(AnimatedText),
     Instruction(ast::Instruction),
 }
 
+// text with effects, which is drawn a glyph at a time
+#[derive(Debug, Clone)]
+struct AnimatedText {
+    text: BasicText,
+    effects: Vec<ast::Effect>,
+    // the tick each glyph was typed on
+    typed_at: Vec<u64>,
+}
+
+impl DialogueSpan {
+    fn new(str: String, color: [f32; 4], effects: &[ast::Effect], typed_at: u64) -> Self {
+        let text = BasicText {
+            pos: (0.0, 0.0),
+            str,
+            color,
+        };
+
+        if effects.is_empty() {
+            DialogueSpan::Text(text)
+        } else {
+            DialogueSpan::Animated(AnimatedText {
+                typed_at: vec![typed_at; text.str.chars().count()],
+                text,
+                effects: effects.to_vec(),
+            })
+        }
+    }
+
+    fn push(&mut self, ch: char, ticks: u64) {
+        match self {
+            Text(t) => t.str.push(ch),
+            DialogueSpan::Animated(a) => {
+                a.text.str.push(ch);
+                a.typed_at.push(ticks);
+            }
+            DialogueSpan::Instruction(_) => {}
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DialogueLine {
     content: Vec<DialogueSpan>,
 }
 
+impl DialogueLine {
+    // renders a whole line at once, for text that shouldn't be typed out
+    fn from_children(children: &[LineChild], color: [f32; 4]) -> Self {
+        let content = children
+            .iter()
+            .filter_map(|child| match child {
+                Span(s) => Some(DialogueSpan::new(
+                    s.text.clone(),
+                    color,
+                    &s.properties.effects,
+                    0,
+                )),
+                // interpolations have already been filled in by DialogueSystem::text
+                LineChild::Instruction(_)
+                | LineChild::Pause(_)
+                | LineChild::Interpolation { .. } => None,
+            })
+            .collect();
+
+        Self { content }
+    }
+}
+
 pub struct DialogueSystem {
-    // chapter: ast::Chapter,
-    it: IntoIter<ast::ChExpr>,
+    story: ast::Story,
+    // the current language's translations, if it isn't the story's own
+    strings: Option<ast::StringTable>,
+    // index of the current chapter in the story
+    chapter: usize,
+    // index of the next expression in the chapter
+    pc: usize,
     linebuf: LineBuffer,
     directive: Directive,
-    voice: String,
+    vars: Variables,
+    world: World,
+    // where each <call> came from, so <return/> knows where to go back to.
+    // chapters are kept by id, like in saves
+    stack: Vec<(String, usize)>,
+    random: Random,
+}
+
+// everything needed to pick the story back up. what was on screen isn't kept,
+// so whatever was interrupted (a line, a prompt..) starts over when it's loaded
+#[derive(Serialize, Deserialize)]
+pub struct Save {
+    chapter: String,
+    pc: usize,
+    vars: Variables,
+    world: World,
+    stack: Vec<(String, usize)>,
+    random: Random,
 }
 
 #[derive(Debug)]
 enum Directive {
     Await,
     OutputLine(OutputLine),
+    Prompt(Prompt),
+    Input(Input),
+    // ticks left to wait
+    Wait(u64),
     None,
+    // the last chapter is over, there's nothing left to say
+    End,
+}
+
+#[derive(Debug)]
+struct Prompt {
+    options: Vec<ast::PromptOption>,
+    selected: usize,
+    // whether up/down/enter were down last tick, so holding a key only counts once
+    held: bool,
+    // what was typed, if this is asking which noun a command meant
+    command: Option<String>,
+}
+
+#[derive(Debug)]
+struct Input {
+    // what it's for
+    into: InputFor,
+    max: usize,
+    // what's been typed so far
+    text: String,
+    // like a prompt's, so the enter that got us here doesn't also finish us
+    held: bool,
 }
 
+#[derive(Debug)]
+enum InputFor {
+    Var(String),
+    Command(Vec<ast::CommandHandler>),
+}
+
+// long enough for "pick up the brass key", short enough to fit on screen
+const COMMAND_MAX: usize = 30;
+
+// calls this deep are almost certainly a chapter calling itself forever
+const MAX_CALL_DEPTH: usize = 64;
+
 #[derive(Debug)]
 struct SpanIter {
     char_iter: IntoIter<char>,
     properties: TextProperties,
+    voice: VoiceStyle,
 }
 
 #[derive(Debug)]
 struct OutputLine {
+    // index of the line in the chapter, and how many of its children have been started
+    idx: usize,
+    started: usize,
     it: IntoIter<LineChild>,
     out: DialogueLine,
     next_update: u64,
@@ -89,19 +232,221 @@ struct OutputLine {
 }
 
 impl DialogueSystem {
-    pub fn init(chapter: ast::Chapter) -> Self {
-        let voice = chapter.voice;
-        let it = chapter.content.into_iter();
+    // the seed decides every <random> from here on
+    pub fn init(story: ast::Story, strings: Option<ast::StringTable>, seed: u64) -> Self {
+        let mut vars = Variables::default();
+        for (name, value) in story.chapters.iter().flat_map(|c| &c.vars) {
+            vars.set(name.clone(), value.clone());
+        }
+        let world = World::new(&story.world);
+
+        let entry = story
+            .chapter_idx(&story.entry)
+            .expect("entry chapter should exist");
 
-        Self {
-            // chapter,
-            it,
+        let mut system = Self {
+            story,
+            strings,
+            chapter: 0,
+            pc: 0,
             linebuf: LineBuffer::new(4),
             directive: Directive::None,
-            voice,
+            vars,
+            world,
+            stack: vec![],
+            random: Random::new(seed),
+        };
+
+        system.enter_chapter(entry, 0);
+        system
+    }
+
+    // swaps in a recompiled story. if the line we're on is still in the chapter,
+    // we carry on from there, otherwise the chapter starts over
+    #[cfg(feature = "debug-reload")]
+    pub fn reload(&mut self, story: ast::Story) {
+        Self::add_new_vars(&mut self.vars, &story);
+        self.world.reload(&story.world);
+
+        let old = &self.story.chapters[self.chapter];
+        let chapter = story.chapter_idx(&old.id);
+        // the line we're on, which we're somewhere just past
+        let anchor = old.content[..self.pc.min(old.content.len())]
+            .iter()
+            .rposition(|expr| matches!(expr, ChExpr::Line { .. }));
+
+        let resume = match (chapter, anchor) {
+            (Some(idx), Some(anchor)) => {
+                let line = &old.content[anchor];
+                let content = &story.chapters[idx].content;
+                // if the line's there more than once, the closest copy is probably it
+                content
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, expr)| *expr == line)
+                    .map(|(i, _)| i)
+                    .min_by_key(|i| (*i as isize - anchor as isize).abs())
+                    .map(|i| (idx, (i + self.pc - anchor).min(content.len())))
+            }
+            _ => None,
+        };
+        let restart = chapter.or_else(|| story.chapter_idx(&story.entry)).unwrap();
+
+        self.story = story;
+        match resume {
+            Some((idx, pc)) => {
+                self.enter_chapter(idx, pc);
+                // a line being typed is always the last thing we ran
+                if let Directive::OutputLine(line) = &mut self.directive {
+                    line.idx = pc - 1;
+                }
+                // a prompt's targets may have moved, so it needs the new ones
+                self.refresh_prompt();
+            }
+            None => {
+                self.enter_chapter(restart, 0);
+                self.directive = Directive::None;
+            }
         }
     }
 
+    // new variables get their defaults, old ones keep their values
+    fn add_new_vars(vars: &mut Variables, story: &ast::Story) {
+        for (name, value) in story.chapters.iter().flat_map(|c| &c.vars) {
+            if vars.get(name).is_none() {
+                vars.set(name.clone(), value.clone());
+            }
+        }
+    }
+
+    pub fn save(&self) -> Save {
+        // anything still going on is run again, from the start
+        let pc = match self.directive {
+            Directive::None | Directive::End => self.pc,
+            _ => self.pc - 1,
+        };
+
+        Save {
+            chapter: self.story.chapters[self.chapter].id.clone(),
+            pc,
+            vars: self.vars.clone(),
+            world: self.world.clone(),
+            stack: self.stack.clone(),
+            random: self.random.clone(),
+        }
+    }
+
+    // saves only know chapters by id, so they survive the story being recompiled,
+    // as long as the chapter's still there
+    pub fn load(&mut self, save: Save) {
+        self.vars = save.vars;
+        Self::add_new_vars(&mut self.vars, &self.story);
+        self.world = save.world;
+        self.world.reload(&self.story.world);
+        self.stack = save.stack;
+        self.random = save.random;
+
+        let (chapter, pc) = match self.story.chapter_idx(&save.chapter) {
+            Some(idx) => (idx, save.pc.min(self.story.chapters[idx].content.len())),
+            None => (self.story.chapter_idx(&self.story.entry).unwrap(), 0),
+        };
+        self.enter_chapter(chapter, pc);
+        self.directive = Directive::None;
+        self.linebuf.clear();
+    }
+
+    // switches language. the line being typed finishes as it was, the next one is translated
+    pub fn set_strings(&mut self, strings: Option<ast::StringTable>) {
+        self.strings = strings;
+        self.refresh_prompt();
+    }
+
+    // the text to show for a line: in the current language if it's been translated,
+    // with its {values} filled in as they are now, so they're typed out like any other text
+    fn text(&self, id: &str, content: Vec<LineChild>) -> Vec<LineChild> {
+        let translated = self.strings.as_ref().and_then(|t| t.lines.get(id));
+        let content = translated.cloned().unwrap_or(content);
+
+        content
+            .into_iter()
+            .map(|child| match child {
+                LineChild::Interpolation { expr, properties } => Span(ast::Span {
+                    text: self.vars.eval(&expr, &self.world).to_string(),
+                    properties,
+                }),
+                child => child,
+            })
+            .collect()
+    }
+
+    fn options(&self, options: Vec<ast::PromptOption>) -> Vec<ast::PromptOption> {
+        options
+            .into_iter()
+            .map(|option| ast::PromptOption {
+                text: self.text(&option.id, option.text),
+                ..option
+            })
+            .collect()
+    }
+
+    // rebuilds the prompt (or input) on screen from the story,
+    // or drops it if it's not there anymore
+    fn refresh_prompt(&mut self) {
+        if let Directive::Input(input) = &mut self.directive {
+            let content = &self.story.chapters[self.chapter].content;
+            // whatever's been typed is kept, as long as it still fits
+            match (
+                self.pc.checked_sub(1).and_then(|pc| content.get(pc)),
+                &mut input.into,
+            ) {
+                (Some(ChExpr::Input { var, max }), InputFor::Var(into)) => {
+                    *into = var.clone();
+                    input.max = *max;
+                    input.text = input.text.chars().take(*max).collect();
+                }
+                (Some(ChExpr::Command { handlers }), InputFor::Command(into)) => {
+                    *into = handlers.clone();
+                }
+                _ => self.directive = Directive::None,
+            }
+        }
+
+        if let Directive::Prompt(prompt) = &self.directive {
+            let content = &self.story.chapters[self.chapter].content;
+            match self.pc.checked_sub(1).and_then(|pc| content.get(pc)) {
+                Some(ChExpr::Prompt { options })
+                    if prompt.command.is_none() && options.len() == prompt.options.len() =>
+                {
+                    let options = self.options(options.clone());
+                    if let Directive::Prompt(prompt) = &mut self.directive {
+                        prompt.options = options;
+                    }
+                }
+                // asking which noun a command meant, which the handlers' targets might've
+                // moved under, so it's asked again
+                Some(ChExpr::Command { handlers }) if prompt.command.is_some() => {
+                    let text = prompt.command.as_deref().unwrap_or_default();
+                    let parsed = command::parse(text, &self.story.vocab, handlers, &self.world);
+                    match parsed {
+                        Parsed::Ambiguous(nouns) if nouns.len() == prompt.options.len() => {
+                            let options = self.noun_options(nouns);
+                            if let Directive::Prompt(prompt) = &mut self.directive {
+                                prompt.options = options;
+                            }
+                        }
+                        _ => self.directive = Directive::None,
+                    }
+                }
+                _ => self.directive = Directive::None,
+            }
+        }
+    }
+
+    fn enter_chapter(&mut self, chapter: usize, pc: usize) {
+        self.chapter = chapter;
+        self.pc = pc;
+    }
+
     pub fn update(&mut self, io: &mut IO) {
         match &mut self.directive {
             Directive::Await => {
@@ -112,9 +457,23 @@ impl DialogueSystem {
             Directive::OutputLine(_) => {
                 self.update_line(io);
             }
+            Directive::Prompt(_) => {
+                self.update_prompt(io);
+            }
+            Directive::Input(_) => {
+                self.update_input(io);
+            }
+            Directive::Wait(ticks) => {
+                if *ticks <= 1 {
+                    self.directive = Directive::None;
+                } else {
+                    *ticks -= 1;
+                }
+            }
             Directive::None => {
-                self.next_directive();
+                self.next_directive(io);
             }
+            Directive::End => {}
         }
     }
 
@@ -125,47 +484,57 @@ impl DialogueSystem {
             if io.ticks >= line.next_update {
                 if let Some(span_iter) = &mut line.wip {
                     if let Some(ch) = span_iter.char_iter.next() {
-                        if let Some(mut span) = line.out.content.last_mut() {
-                            if let Text(t) = &mut span {
-                                t.str.push(ch);
-                            }
-                            line.next_update =
-                                io.ticks + (2 * (6 - span_iter.properties.speed) as u64);
-                        } else {
-                            line.out.content.push(DialogueSpan::Text(BasicText {
-                                pos: (0.0, 0.0),
-                                str: ch.to_string(),
-                                color: [1.0, 1.0, 1.0, 1.0], // TODO: voice, smh
-                            }));
-                            line.next_update =
-                                io.ticks + (2 * (6 - span_iter.properties.speed) as u64);
+                        if let Some(span) = line.out.content.last_mut() {
+                            span.push(ch, io.ticks);
                         }
+                        line.next_update =
+                            io.ticks + span_iter.voice.delay(span_iter.properties.speed);
 
                         if ch == ' ' {
                             line.next_update = io.ticks + 2;
                             retry = true;
-                        } else {
-                            io.audio_tx.send(AudioSysMsg::PlayEffect(0));
+                        } else if let Some(blip) = span_iter.voice.blip {
+                            io.audio_tx
+                                .send(AudioSysMsg::PlayEffect(blip))
+                                .unwrap_or(());
                         }
                     } else {
                         line.wip = None;
                     }
                 } else {
                     if let Some(child) = line.it.next() {
+                        line.started += 1;
                         match child {
                             Span(s) => {
                                 let char_iter = s.text.chars().collect::<Vec<_>>().into_iter();
+                                let voice =
+                                    VoiceStyle::resolve(&self.story.voices, &s.properties.voice);
+                                // each span gets its own text, so it can have its own colour
+                                line.out.content.push(DialogueSpan::new(
+                                    String::new(),
+                                    voice.color,
+                                    &s.properties.effects,
+                                    io.ticks,
+                                ));
                                 let properties = s.properties;
                                 line.wip = Some(SpanIter {
                                     char_iter,
                                     properties,
+                                    voice,
                                 });
                             }
+                            LineChild::Pause(ticks) => {
+                                // hold the next character back, without typing one now
+                                line.next_update = io.ticks + ticks;
+                            }
                             LineChild::Instruction(i) => {
-                                // TODO: do instruction
-                                // TODO: call this fn again
-                                // line.update(io, directive);
+                                // fire it between the characters it sits between,
+                                // then carry on with the line this tick
+                                run_instruction(io, &i);
+                                retry = true;
                             }
+                            // these were filled in when the line started
+                            LineChild::Interpolation { .. } => retry = true,
                         }
                     } else {
                         new_directive = Some(Directive::None);
@@ -183,51 +552,388 @@ impl DialogueSystem {
         }
     }
 
-    fn next_directive(&mut self) {
-        match self.it.next().expect("new chapter should open before eof") {
+    fn update_prompt(&mut self, io: &mut IO) {
+        let mut chosen = None;
+        if let Directive::Prompt(prompt) = &mut self.directive {
+            let controls = &io.controls;
+            let n = prompt.options.len();
+
+            if !prompt.held {
+                if controls.up {
+                    prompt.selected = (prompt.selected + n - 1) % n;
+                } else if controls.down {
+                    prompt.selected = (prompt.selected + 1) % n;
+                } else if controls.enter {
+                    chosen = Some(prompt.selected);
+                }
+            }
+
+            prompt.held = controls.up || controls.down || controls.enter;
+        }
+
+        if let Some(idx) = chosen {
+            if let Directive::Prompt(prompt) =
+                std::mem::replace(&mut self.directive, Directive::None)
+            {
+                let option = &prompt.options[idx];
+                // echo the choice back, then carry on from its content
+                self.linebuf.push(DialogueLine::from_children(
+                    &option.text,
+                    [1.0, 1.0, 1.0, 1.0],
+                ));
+                self.pc = option.target;
+            }
+        }
+    }
+
+    fn update_input(&mut self, io: &mut IO) {
+        let mut done = false;
+        if let Directive::Input(input) = &mut self.directive {
+            for ch in io.controls.input_char.chars() {
+                match ch {
+                    // backspace, or delete on macs
+                    '\u{8}' | '\u{7f}' => {
+                        input.text.pop();
+                    }
+                    // enter comes through here too, but it's handled below
+                    ch if ch.is_control() => {}
+                    ch if input.text.chars().count() < input.max => input.text.push(ch),
+                    _ => {}
+                }
+            }
+
+            // there has to be something to store
+            done = io.controls.enter && !input.held && !input.text.trim().is_empty();
+            input.held = io.controls.enter;
+        }
+
+        if done {
+            if let Directive::Input(input) = std::mem::replace(&mut self.directive, Directive::None)
+            {
+                let text = input.text.trim().to_string();
+                // echo it back, like a prompt's choice. commands look like they did being typed
+                let echo = match input.into {
+                    InputFor::Var(_) => text.clone(),
+                    InputFor::Command(_) => format!("> {}", text),
+                };
+                self.linebuf.push(DialogueLine {
+                    content: vec![Text(BasicText {
+                        pos: (0.0, 0.0),
+                        str: echo,
+                        color: [1.0, 1.0, 1.0, 1.0],
+                    })],
+                });
+
+                match input.into {
+                    InputFor::Var(var) => self.vars.set(var, Value::Str(text)),
+                    InputFor::Command(handlers) => self.run_command(&text, &handlers),
+                }
+            }
+        }
+    }
+
+    // continues from whichever handler the command meant. if it's not clear which noun
+    // it meant, the player picks one, and if it didn't mean anything we just carry on
+    fn run_command(&mut self, text: &str, handlers: &[ast::CommandHandler]) {
+        match command::parse(text, &self.story.vocab, handlers, &self.world) {
+            Parsed::Matched(target) => self.pc = target,
+            Parsed::Ambiguous(nouns) => {
+                self.directive = Directive::Prompt(Prompt {
+                    options: self.noun_options(nouns),
+                    selected: 0,
+                    held: true,
+                    command: Some(text.to_string()),
+                });
+            }
+            Parsed::NotUnderstood => {}
+        }
+    }
+
+    // nouns aren't translated, so these are just the words
+    fn noun_options(&self, nouns: Vec<(String, usize)>) -> Vec<ast::PromptOption> {
+        let chapter = &self.story.chapters[self.chapter];
+        nouns
+            .into_iter()
+            .map(|(noun, target)| ast::PromptOption {
+                id: String::new(),
+                text: vec![Span(ast::Span {
+                    text: noun,
+                    properties: TextProperties {
+                        speed: 0,
+                        voice: chapter.voice.clone(),
+                        effects: vec![],
+                    },
+                })],
+                target,
+            })
+            .collect()
+    }
+
+    fn next_directive(&mut self, io: &mut IO) {
+        let chapter = &self.story.chapters[self.chapter];
+        let expr = match chapter.content.get(self.pc) {
+            Some(expr) => expr.clone(),
+            None => {
+                // fall through to the next chapter, if there is one
+                if self.chapter + 1 < self.story.chapters.len() {
+                    self.enter_chapter(self.chapter + 1, 0);
+                } else {
+                    self.directive = Directive::End;
+                }
+                return;
+            }
+        };
+        self.pc += 1;
+
+        match expr {
             ChExpr::Action(action) => match action {
                 Action::Await => self.directive = Directive::Await,
             },
-            ChExpr::Instruction(instruction) => match instruction {
-                Instruction::Play { sound } => {
-                    // TODO: play a sound
-                }
-            },
-            ChExpr::Line { content } => {
+            ChExpr::Instruction(instruction) => run_instruction(io, &instruction),
+            ChExpr::Wait(ticks) => {
+                self.directive = Directive::Wait(ticks);
+            }
+            ChExpr::Line { id, content } => {
                 self.directive = Directive::OutputLine(OutputLine {
-                    it: content.into_iter(),
+                    idx: self.pc - 1,
+                    started: 0,
+                    it: self.text(&id, content).into_iter(),
                     out: DialogueLine { content: vec![] },
                     next_update: 0,
                     wip: None,
                 });
                 self.linebuf.push(DialogueLine { content: vec![] });
             }
+            ChExpr::Prompt { options } => {
+                self.directive = Directive::Prompt(Prompt {
+                    options: self.options(options),
+                    selected: 0,
+                    held: true,
+                    command: None,
+                });
+            }
+            ChExpr::Jump(target) => {
+                self.pc = target;
+            }
+            ChExpr::GotoChapter { chapter, label } => {
+                // ir-parser checks these exist when it compiles the story
+                let idx = self
+                    .story
+                    .chapter_idx(&chapter)
+                    .expect("goto to undefined chapter");
+                let pc = label.map_or(0, |label| self.story.chapters[idx].labels[&label]);
+                self.enter_chapter(idx, pc);
+            }
+            ChExpr::Call { chapter, label } => {
+                if self.stack.len() >= MAX_CALL_DEPTH {
+                    // carry on as if it had already returned, rather than hang
+                    self.runtime_error(&format!(
+                        "call to {} is {} calls deep, is something calling itself?",
+                        label, MAX_CALL_DEPTH
+                    ));
+                    return;
+                }
+                let here = self.story.chapters[self.chapter].id.clone();
+                self.stack.push((here, self.pc));
+
+                // ir-parser checks these exist too
+                let idx = match chapter {
+                    Some(chapter) => self
+                        .story
+                        .chapter_idx(&chapter)
+                        .expect("call to undefined chapter"),
+                    None => self.chapter,
+                };
+                let pc = self.story.chapters[idx].labels[&label];
+                self.enter_chapter(idx, pc);
+            }
+            ChExpr::Return => match self.stack.pop() {
+                // the chapter could've gone away in a reload, in which case start it over
+                Some((chapter, pc)) => match self.story.chapter_idx(&chapter) {
+                    Some(idx) => {
+                        let pc = pc.min(self.story.chapters[idx].content.len());
+                        self.enter_chapter(idx, pc);
+                    }
+                    None => {
+                        let entry = self.story.chapter_idx(&self.story.entry).unwrap();
+                        self.enter_chapter(entry, 0);
+                    }
+                },
+                None => self.runtime_error("<return/> without a <call>"),
+            },
+            ChExpr::Random { mode, variants } => {
+                let here = (self.story.chapters[self.chapter].id.clone(), self.pc - 1);
+                let pick = self.random.pick(here, &mode, &variants);
+                self.pc = variants[pick].target;
+            }
+            ChExpr::Set { var, value } => {
+                let value = self.vars.eval(&value, &self.world);
+                self.vars.set(var, value);
+            }
+            ChExpr::Branch { cond, otherwise } => {
+                if !self.vars.eval_bool(&cond, &self.world) {
+                    self.pc = otherwise;
+                }
+            }
+            ChExpr::Input { var, max } => {
+                // start from what it is now, so there's something to edit
+                let text = match self.vars.get(&var) {
+                    Some(Value::Str(text)) => text.chars().take(max).collect(),
+                    _ => String::new(),
+                };
+                self.directive = Directive::Input(Input {
+                    into: InputFor::Var(var),
+                    max,
+                    text,
+                    held: true,
+                });
+            }
+            ChExpr::Go(destination) => self.world.go(&destination),
+            ChExpr::Move { item, to } => self.world.move_item(&item, &to),
+            ChExpr::SetState { item, state } => self.world.set_state(&item, &state),
+            ChExpr::Command { handlers } => {
+                self.directive = Directive::Input(Input {
+                    into: InputFor::Command(handlers),
+                    max: COMMAND_MAX,
+                    text: String::new(),
+                    held: true,
+                });
+            }
         }
     }
 
     pub fn draw(&mut self, io: &mut IO) {
-        for (idx, line) in self.linebuf.lines.iter().enumerate() {
-            if line.is_none() {
-                continue;
+        let mut rows: Vec<Option<DialogueLine>> = self.linebuf.lines.clone();
+
+        if let Directive::Prompt(prompt) = &self.directive {
+            for (idx, option) in prompt.options.iter().enumerate() {
+                let (cursor, color) = if idx == prompt.selected {
+                    ("> ", [1.0, 1.0, 1.0, 1.0])
+                } else {
+                    ("  ", [0.5, 0.5, 0.5, 1.0])
+                };
+
+                let mut line = DialogueLine::from_children(&option.text, color);
+                line.content.insert(
+                    0,
+                    DialogueSpan::Text(BasicText {
+                        pos: (0.0, 0.0),
+                        str: cursor.to_string(),
+                        color,
+                    }),
+                );
+                rows.push(Some(line));
             }
 
-            let y: f32 = 226.0 + (idx * 12) as f32;
+            // options push older lines off the top, unless there are more options than lines
+            let visible = self.linebuf.lines.len().max(prompt.options.len());
+            rows.drain(..rows.len() - visible);
+        }
+
+        if let Directive::Input(input) = &self.directive {
+            // the cursor blinks a couple of times a second
+            let cursor = if io.ticks % 30 < 15 { "_" } else { "" };
+            rows.push(Some(DialogueLine {
+                content: vec![Text(BasicText {
+                    pos: (0.0, 0.0),
+                    str: format!("> {}{}", input.text, cursor),
+                    color: [1.0, 1.0, 1.0, 1.0],
+                })],
+            }));
+            rows.remove(0);
+        }
+
+        let top: f32 = 226.0 - (rows.len() - self.linebuf.lines.len()) as f32 * 12.0;
+
+        for (idx, line) in rows.iter().enumerate() {
+            let y: f32 = top + (idx * 12) as f32;
             let mut x: f32 = 12.0;
             if let Some(lineee) = &line {
                 for span in &lineee.content {
-                    if let Text(tmp) = span {
-                        let text = BasicText {
-                            pos: (x + tmp.pos.0, y + tmp.pos.1),
-                            str: tmp.str.clone(),
-                            color: tmp.color,
-                        };
+                    match span {
+                        Text(tmp) => {
+                            let text = BasicText {
+                                pos: (x + tmp.pos.0, y + tmp.pos.1),
+                                str: tmp.str.clone(),
+                                color: tmp.color,
+                            };
+
+                            x += (text.str.chars().count() * 8) as f32;
+
+                            io.draw_queue.push_back(DrawString(text));
+                        }
+                        DialogueSpan::Animated(a) => {
+                            for (i, ch) in a.text.str.chars().enumerate() {
+                                let age = io.ticks.saturating_sub(a.typed_at[i]);
+                                let (offset, color) =
+                                    effects::apply(&a.effects, io.ticks, i, age, a.text.color);
 
-                        x += (text.str.len() * 8) as f32;
+                                io.draw_queue.push_back(DrawChar(BasicText {
+                                    pos: (x + offset.0, y + offset.1),
+                                    str: ch.to_string(),
+                                    color,
+                                }));
 
-                        io.draw_queue.push_back(DrawString(text));
+                                x += 8.0;
+                            }
+                        }
+                        DialogueSpan::Instruction(_) => {}
                     }
                 }
             }
         }
+
+        #[cfg(debug_assertions)]
+        if io.controls.debug {
+            if let Some(loc) = self.source_loc() {
+                io.draw_queue.push_back(DrawString(BasicText {
+                    pos: (12.0, 12.0),
+                    str: loc,
+                    color: [0.5, 0.5, 0.5, 1.0],
+                }));
+            }
+        }
+    }
+
+    // mistakes in the story that ir-parser can't catch. they're reported, and the story carries on
+    fn runtime_error(&self, msg: &str) {
+        #[cfg(debug_assertions)]
+        if let Some(loc) = self.source_loc() {
+            eprintln!("{}: {}", loc, msg);
+            return;
+        }
+        let chapter = &self.story.chapters[self.chapter].id;
+        eprintln!("in chapter {}: {}", chapter, msg);
+    }
+
+    // where whatever we're doing came from, e.g. "intro.xml:7:12" for the span being typed
+    #[cfg(debug_assertions)]
+    fn source_loc(&self) -> Option<String> {
+        let source = self.story.chapters[self.chapter].source.as_ref()?;
+        let loc = match &self.directive {
+            Directive::OutputLine(line) => {
+                let child = line.started.checked_sub(1);
+                child
+                    .and_then(|child| source.children.get(line.idx)?.get(child))
+                    .or_else(|| source.exprs.get(line.idx))?
+            }
+            _ => source.exprs.get(self.pc.checked_sub(1)?)?,
+        };
+        Some(format!("{}:{}:{}", source.file, loc.line, loc.col))
+    }
+}
+
+// instructions don't touch the dialogue itself, they just poke other systems
+fn run_instruction(io: &mut IO, instruction: &Instruction) {
+    let msg = match instruction {
+        Instruction::Play { sound } => resources::song_id(sound).map(AudioSysMsg::PlayMusic),
+        Instruction::Effect { sound } => resources::effect_id(sound).map(AudioSysMsg::PlayEffect),
+        Instruction::Stop => Some(AudioSysMsg::StopMusic),
+    };
+
+    match msg {
+        // the audio thread only goes away when we're shutting down anyway
+        Some(msg) => io.audio_tx.send(msg).unwrap_or(()),
+        None => eprintln!("no such sound: {:?}", instruction),
     }
 }
@@ -0,0 +1,194 @@
+use ir::ast::{RandomMode, Variant};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// picks <random> variants. it's all in saves, so a loaded game rolls the same as it did
+// the first time, and the same seed always gives the same playthrough
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Random {
+    state: u64,
+    // the variants each shuffle or cycle has used since it last started over,
+    // by chapter id and the <random>'s index
+    rounds: BTreeMap<(String, usize), Vec<usize>>,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: seed,
+            rounds: BTreeMap::new(),
+        }
+    }
+
+    // the index of the variant to continue from
+    pub fn pick(&mut self, at: (String, usize), mode: &RandomMode, variants: &[Variant]) -> usize {
+        let mut round = self.rounds.remove(&at).unwrap_or_default();
+        // a reload could've taken variants away
+        round.retain(|&v| v < variants.len());
+
+        let pick = match mode {
+            RandomMode::Weighted => return self.weighted(variants.iter().enumerate()),
+            RandomMode::Cycle => {
+                if round.len() == variants.len() {
+                    round.clear();
+                }
+                round.len()
+            }
+            RandomMode::Shuffle => {
+                let skip: Vec<usize> = if round.len() == variants.len() {
+                    // a new round, which shouldn't start with how the last one ended
+                    let last = round.pop().filter(|_| variants.len() > 1);
+                    round.clear();
+                    last.into_iter().collect()
+                } else {
+                    round.clone()
+                };
+                self.weighted(unused(variants, &skip))
+            }
+        };
+
+        round.push(pick);
+        self.rounds.insert(at, round);
+        pick
+    }
+
+    fn weighted<'a>(
+        &mut self,
+        variants: impl Iterator<Item = (usize, &'a Variant)> + Clone,
+    ) -> usize {
+        let total: u64 = variants.clone().map(|(_, v)| v.weight as u64).sum();
+        let mut roll = self.next() % total.max(1);
+        for (idx, variant) in variants.clone() {
+            if roll < variant.weight as u64 {
+                return idx;
+            }
+            roll -= variant.weight as u64;
+        }
+        variants.map(|(idx, _)| idx).next().unwrap_or(0)
+    }
+
+    // splitmix64, which is tiny and good enough for dialogue
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+fn unused<'a>(
+    variants: &'a [Variant],
+    round: &'a [usize],
+) -> impl Iterator<Item = (usize, &'a Variant)> + Clone {
+    variants
+        .iter()
+        .enumerate()
+        .filter(move |(idx, _)| !round.contains(idx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Random;
+    use ir::ast::{RandomMode, Variant};
+
+    fn variants(weights: &[u32]) -> Vec<Variant> {
+        let variant = |(target, &weight)| Variant { weight, target };
+        weights.iter().enumerate().map(variant).collect()
+    }
+
+    fn picks(random: &mut Random, mode: RandomMode, variants: &[Variant], n: usize) -> Vec<usize> {
+        let at = || ("intro".to_string(), 0);
+        (0..n).map(|_| random.pick(at(), &mode, variants)).collect()
+    }
+
+    #[test]
+    fn seeded() {
+        let v = variants(&[1, 2, 3, 4]);
+        let a = picks(&mut Random::new(42), RandomMode::Weighted, &v, 50);
+        assert_eq!(a, picks(&mut Random::new(42), RandomMode::Weighted, &v, 50));
+        assert_ne!(a, picks(&mut Random::new(43), RandomMode::Weighted, &v, 50));
+    }
+
+    #[test]
+    fn weighted() {
+        let v = variants(&[0, 3, 0, 1]);
+        let picks = picks(&mut Random::new(7), RandomMode::Weighted, &v, 1000);
+        assert!(picks.iter().all(|&p| p == 1 || p == 3));
+        // roughly 3:1
+        let ones = picks.iter().filter(|&&p| p == 1).count();
+        assert!((650..850).contains(&ones), "{}", ones);
+    }
+
+    #[test]
+    fn shuffle() {
+        let v = variants(&[1, 5, 1, 1]);
+        for seed in 0..20 {
+            let picks = picks(&mut Random::new(seed), RandomMode::Shuffle, &v, 40);
+            // every round has every variant once
+            for round in picks.chunks(v.len()) {
+                let mut round = round.to_vec();
+                round.sort_unstable();
+                assert_eq!(round, vec![0, 1, 2, 3]);
+            }
+            // and never repeats, even where one round ends and the next starts
+            assert!(picks.windows(2).all(|w| w[0] != w[1]), "{:?}", picks);
+        }
+
+        // with one variant there's nothing else to say
+        let one = variants(&[1]);
+        assert_eq!(
+            picks(&mut Random::new(0), RandomMode::Shuffle, &one, 3),
+            vec![0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn cycle() {
+        let v = variants(&[1, 9, 1]);
+        assert_eq!(
+            picks(&mut Random::new(0), RandomMode::Cycle, &v, 7),
+            vec![0, 1, 2, 0, 1, 2, 0]
+        );
+    }
+
+    #[test]
+    fn rounds_are_per_random() {
+        let v = variants(&[1, 1, 1]);
+        let mut random = Random::new(0);
+        assert_eq!(
+            random.pick(("intro".to_string(), 0), &RandomMode::Cycle, &v),
+            0
+        );
+        assert_eq!(
+            random.pick(("intro".to_string(), 5), &RandomMode::Cycle, &v),
+            0
+        );
+        assert_eq!(
+            random.pick(("hall".to_string(), 0), &RandomMode::Cycle, &v),
+            0
+        );
+        assert_eq!(
+            random.pick(("intro".to_string(), 0), &RandomMode::Cycle, &v),
+            1
+        );
+    }
+
+    #[test]
+    fn quicksave() {
+        let v = variants(&[1, 2, 3]);
+        let mut random = Random::new(1234);
+        picks(&mut random, RandomMode::Shuffle, &v, 4);
+        picks(&mut random, RandomMode::Weighted, &v, 5);
+
+        // quicksaves go through the same bundle format
+        let bytes = ir::bundle::encode(&random, "test");
+        let mut loaded: Random = ir::bundle::decode(&bytes).unwrap();
+        for mode in &[RandomMode::Shuffle, RandomMode::Weighted, RandomMode::Cycle] {
+            assert_eq!(
+                picks(&mut random, mode.clone(), &v, 20),
+                picks(&mut loaded, mode.clone(), &v, 20)
+            );
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use crate::dialogue::world::World;
+use ir::ast::{BinOp, Expr, UnOp, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// story variables, set by the dialogue and checked by its branches
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Variables {
+    values: HashMap<String, Value>,
+}
+
+impl Variables {
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    pub fn set(&mut self, name: String, value: Value) {
+        self.values.insert(name, value);
+    }
+
+    // expressions were type checked by ir-parser, so a mismatch here means the IR is broken
+    pub fn eval(&self, expr: &Expr, world: &World) -> Value {
+        match expr {
+            Expr::Lit(value) => value.clone(),
+            Expr::Var(name, ty) => self.get(name).cloned().unwrap_or_else(|| ty.zero()),
+            Expr::Query(query) => world.query(query),
+            Expr::Unary(op, expr) => match (op, self.eval(expr, world)) {
+                (UnOp::Not, Value::Bool(b)) => Value::Bool(!b),
+                (UnOp::Neg, Value::Int(n)) => Value::Int(n.wrapping_neg()),
+                (op, v) => panic!("can't apply {:?} to {:?}", op, v),
+            },
+            // && and || only evaluate their rhs if they need to
+            Expr::Binary(BinOp::And, lhs, rhs) => {
+                Value::Bool(self.eval_bool(lhs, world) && self.eval_bool(rhs, world))
+            }
+            Expr::Binary(BinOp::Or, lhs, rhs) => {
+                Value::Bool(self.eval_bool(lhs, world) || self.eval_bool(rhs, world))
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                Self::binary(*op, self.eval(lhs, world), self.eval(rhs, world))
+            }
+        }
+    }
+
+    pub fn eval_bool(&self, expr: &Expr, world: &World) -> bool {
+        match self.eval(expr, world) {
+            Value::Bool(b) => b,
+            v => panic!("expected a bool, found {:?}", v),
+        }
+    }
+
+    fn binary(op: BinOp, lhs: Value, rhs: Value) -> Value {
+        match (op, lhs, rhs) {
+            (BinOp::Eq, a, b) => Value::Bool(a == b),
+            (BinOp::Ne, a, b) => Value::Bool(a != b),
+            (BinOp::Add, Value::Str(a), Value::Str(b)) => Value::Str(a + &b),
+            (op, Value::Int(a), Value::Int(b)) => match op {
+                BinOp::Add => Value::Int(a.wrapping_add(b)),
+                BinOp::Sub => Value::Int(a.wrapping_sub(b)),
+                BinOp::Mul => Value::Int(a.wrapping_mul(b)),
+                // dividing by zero gives zero rather than crashing the story
+                BinOp::Div => Value::Int(a.checked_div(b).unwrap_or(0)),
+                BinOp::Rem => Value::Int(a.checked_rem(b).unwrap_or(0)),
+                BinOp::Lt => Value::Bool(a < b),
+                BinOp::Le => Value::Bool(a <= b),
+                BinOp::Gt => Value::Bool(a > b),
+                BinOp::Ge => Value::Bool(a >= b),
+                op => panic!("can't apply {:?} to ints", op),
+            },
+            (op, a, b) => panic!("can't apply {:?} to {:?} and {:?}", op, a, b),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use crate::resources;
+use ir::ast;
+use std::collections::BTreeMap;
+
+// a voice, resolved into what the renderer and audio system need
+#[derive(Debug, Clone)]
+pub struct VoiceStyle {
+    pub color: [f32; 4],
+    pub blip: Option<usize>,
+    pub speed: f32,
+}
+
+impl Default for VoiceStyle {
+    fn default() -> Self {
+        Self {
+            color: [1.0, 1.0, 1.0, 1.0],
+            blip: resources::effect_id("blip"),
+            speed: 1.0,
+        }
+    }
+}
+
+impl VoiceStyle {
+    // ir-parser checks that voices exist, but stories without a voices file get the default
+    pub fn resolve(voices: &BTreeMap<String, ast::Voice>, name: &str) -> Self {
+        let voice = match voices.get(name) {
+            Some(voice) => voice,
+            None => return Self::default(),
+        };
+
+        let [r, g, b, a] = voice.color;
+        Self {
+            color: [
+                r as f32 / 255.0,
+                g as f32 / 255.0,
+                b as f32 / 255.0,
+                a as f32 / 255.0,
+            ],
+            blip: voice.blip.as_deref().and_then(resources::effect_id),
+            speed: voice.speed,
+        }
+    }
+
+    // how many ticks to wait after typing a character at the given speed
+    pub fn delay(&self, speed: u32) -> u64 {
+        let ticks = (2 * (6 - speed)) as f32 / self.speed;
+        ticks.round() as u64
+    }
+}
@@ -0,0 +1,99 @@
+use ir::ast::{Destination, Item, Place, Query, Room, Value, INVENTORY};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// where the player is and where everything's got to. the map itself is the story's,
+// so only that much goes in a save
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct World {
+    room: String,
+    items: BTreeMap<String, Item>,
+    #[serde(skip)]
+    rooms: BTreeMap<String, Room>,
+}
+
+impl World {
+    pub fn new(world: &ir::ast::World) -> Self {
+        Self {
+            room: world.start.clone(),
+            items: world.items.clone(),
+            rooms: world.rooms.clone(),
+        }
+    }
+
+    // swaps in the story's map, e.g. after a reload or loading a save.
+    // new items start where the story says, and anything that's gone is forgotten
+    pub fn reload(&mut self, world: &ir::ast::World) {
+        self.rooms = world.rooms.clone();
+        if !self.rooms.contains_key(&self.room) {
+            self.room = world.start.clone();
+        }
+
+        let rooms = &self.rooms;
+        let mut items = world.items.clone();
+        for (id, item) in &mut items {
+            match self.items.remove(id) {
+                Some(old) if old.location == INVENTORY || rooms.contains_key(&old.location) => {
+                    *item = old
+                }
+                _ => {}
+            }
+        }
+        self.items = items;
+    }
+
+    // queries were checked against the world by ir-parser
+    pub fn query(&self, query: &Query) -> Value {
+        let item = |id: &str| self.items.get(id);
+        match query {
+            Query::Here => Value::Str(self.room.clone()),
+            Query::Has(id) => Value::Bool(matches!(item(id), Some(i) if i.location == INVENTORY)),
+            Query::Location(id) => {
+                Value::Str(item(id).map_or(String::new(), |i| i.location.clone()))
+            }
+            Query::State(id) => Value::Str(item(id).map_or(String::new(), |i| i.state.clone())),
+            Query::Exit(dir) => Value::Str(self.exit(dir).unwrap_or_default()),
+        }
+    }
+
+    // whether the player can see or reach an item
+    pub fn is_present(&self, id: &str) -> bool {
+        let location = self.items.get(id).map(|i| &i.location);
+        matches!(location, Some(l) if l == INVENTORY || *l == self.room)
+    }
+
+    pub fn is_item(&self, id: &str) -> bool {
+        self.items.contains_key(id)
+    }
+
+    pub fn go(&mut self, destination: &Destination) {
+        let room = match destination {
+            Destination::Room(room) => Some(room.clone()),
+            Destination::Exit(dir) => self.exit(dir),
+        };
+        if let Some(room) = room {
+            self.room = room;
+        }
+    }
+
+    pub fn move_item(&mut self, id: &str, to: &Place) {
+        let location = match to {
+            Place::Room(room) => room.clone(),
+            Place::Inventory => INVENTORY.to_string(),
+            Place::Here => self.room.clone(),
+        };
+        if let Some(item) = self.items.get_mut(id) {
+            item.location = location;
+        }
+    }
+
+    pub fn set_state(&mut self, id: &str, state: &str) {
+        if let Some(item) = self.items.get_mut(id) {
+            item.state = state.to_string();
+        }
+    }
+
+    fn exit(&self, dir: &str) -> Option<String> {
+        self.rooms.get(&self.room)?.exits.get(dir).cloned()
+    }
+}
@@ -2,7 +2,7 @@ use crate::graphics::text::BasicText;
 
 pub enum DrawCommand {
     DrawBg,
-    DrawChar,
+    DrawChar(BasicText),
     DrawString(BasicText),
     //TODO: drawsprite?
 }
\ No newline at end of file
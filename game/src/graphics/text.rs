@@ -28,6 +28,12 @@ impl TextRenderContext {
     }
 
     pub fn draw(&mut self, f_ctx: &mut FrameContext, text: BasicText) {
+        self.queue(&text);
+        self.flush(f_ctx);
+    }
+
+    // queued text isn't drawn until the next flush
+    pub fn queue(&mut self, text: &BasicText) {
         let section = Section {
             screen_position: text.pos,
             text: vec![Text::new(&text.str).with_scale(8.0).with_color(text.color)],
@@ -35,7 +41,9 @@ impl TextRenderContext {
         };
 
         self.glyph_brush.queue(section);
+    }
 
+    pub fn flush(&mut self, f_ctx: &mut FrameContext) {
         let mut staging_belt = wgpu::util::StagingBelt::new(0x400);
 
         self.glyph_brush
@@ -2,3 +2,16 @@ pub const BG: &[u8] = include_bytes!("bg.png");
 pub const FONT: &[u8] = include_bytes!("PressStart2P.ttf");
 pub const MUSIC: &[u8] = include_bytes!("lowtide.ogg");
 pub const EFFECT_BLIP: &[u8] = include_bytes!("blip2.mp3");
+
+// songs and sound effects, by the names dialogue uses for them
+pub const SONGS: &[(&str, &[u8])] = &[("lowtide", MUSIC)];
+
+pub const EFFECTS: &[(&str, &[u8])] = &[("blip", EFFECT_BLIP)];
+
+pub fn song_id(name: &str) -> Option<usize> {
+    SONGS.iter().position(|(n, _)| *n == name)
+}
+
+pub fn effect_id(name: &str) -> Option<usize> {
+    EFFECTS.iter().position(|(n, _)| *n == name)
+}
@@ -12,8 +12,8 @@ pub enum AudioSysMsg {
     _SetMusicVolume(f32),
     _SetEffectsVolume(f32),
 
-    _PlayMusic,
-    _StopMusic,
+    PlayMusic(usize),
+    StopMusic,
 
     PlayEffect(usize),
     _StopEffect(usize),
@@ -35,14 +35,8 @@ impl AudioSystem {
     fn init() -> Self {
         let (_stream, stream_handle) = rodio::OutputStream::try_default().unwrap();
 
-        let music_source =
-            rodio::Decoder::new(BufReader::new(std::io::Cursor::new(resources::MUSIC)))
-                .unwrap()
-                .speed(0.5)
-                .repeat_infinite();
-
+        // music waits for the dialogue to ask for it
         let music_sink = rodio::Sink::try_new(&stream_handle).expect("could not create music sink");
-        music_sink.append(music_source);
 
         // let src = Box::new(
         //     rodio::Decoder::new(BufReader::new(std::io::Cursor::new(resources::MUSIC)))
@@ -88,13 +82,26 @@ impl AudioSystem {
             AudioSysMsg::_SetMasterVolume(_) => {}
             AudioSysMsg::_SetMusicVolume(_) => {}
             AudioSysMsg::_SetEffectsVolume(_) => {}
-            AudioSysMsg::_PlayMusic => {}
-            AudioSysMsg::_StopMusic => {}
+            AudioSysMsg::PlayMusic(id) => {
+                let (_, song) = resources::SONGS[id];
+                let src = rodio::Decoder::new(BufReader::new(std::io::Cursor::new(song)))
+                    .unwrap()
+                    .speed(0.5)
+                    .repeat_infinite();
+
+                // dropping the old sink stops whatever was playing
+                self.music =
+                    rodio::Sink::try_new(&self.stream_handle).expect("could not create music sink");
+                self.music.append(src);
+            }
+            AudioSysMsg::StopMusic => {
+                self.music.stop();
+            }
             AudioSysMsg::PlayEffect(id) => {
-                let src = rodio::Decoder::new(BufReader::new(std::io::Cursor::new(
-                    resources::EFFECT_BLIP,
-                )))
-                .unwrap(); // TODO: find out if a buffered source is useful at all
+                let (_, effect) = resources::EFFECTS[id];
+                // TODO: find out if a buffered source is useful at all
+                let src =
+                    rodio::Decoder::new(BufReader::new(std::io::Cursor::new(effect))).unwrap();
 
                 let sink = rodio::Sink::try_new(&self.stream_handle)
                     .expect("could not create effect sink");
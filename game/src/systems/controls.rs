@@ -7,7 +7,16 @@ pub struct Controls {
     pub left: bool,
     pub right: bool,
     pub shift: bool,
-    pub caps: bool
+    pub caps: bool,
+    // whether the debug overlay is showing
+    pub debug: bool,
+    // which language is selected, counting up with each press of F2
+    pub lang: usize,
+    // characters typed since the last tick, including backspaces
+    pub input_char: String,
+    // F5 and F9, until the game gets round to saving or loading
+    pub save: bool,
+    pub load: bool
 }
 
 impl Controls {
@@ -21,6 +30,10 @@ impl Controls {
             VirtualKeyCode::LShift => { self.shift = true; }
             VirtualKeyCode::RShift => { self.shift = true; }
             VirtualKeyCode::Capital => { self.caps = !self.caps; } // hmmmm
+            VirtualKeyCode::F2 => { self.lang = self.lang.wrapping_add(1); }
+            VirtualKeyCode::F3 => { self.debug = !self.debug; }
+            VirtualKeyCode::F5 => { self.save = true; }
+            VirtualKeyCode::F9 => { self.load = true; }
             _ => {}
         }
     }
@@ -42,14 +55,18 @@ impl Controls {
 impl Default for Controls {
     fn default() -> Self {
         Self {
-            //input_char: String::new(),
             enter: false,
             up: false,
             down: false,
             left: false,
             right: false,
             shift: false,
-            caps: false
+            caps: false,
+            debug: false,
+            lang: 0,
+            input_char: String::new(),
+            save: false,
+            load: false
         }
     }
 }
\ No newline at end of file
@@ -1,15 +1,20 @@
-use crate::dialogue::DialogueSystem;
+use crate::dialogue::{DialogueSystem, Save};
 use crate::graphics::background::BgRenderContext;
 use crate::graphics::draw::DrawCommand;
 use crate::graphics::text::TextRenderContext;
 use crate::graphics::{FrameContext, GraphicsContext};
 use crate::systems::audio::{AudioSysMsg, AudioSystem};
 use crate::systems::controls::Controls;
-use bincode;
+#[cfg(feature = "debug-reload")]
+use crate::systems::reload::ReloadSystem;
 use crossbeam_channel::Sender;
+use ir::ast::{Story, StringTable};
+use ir::bundle::LoadError;
+use serde::de::DeserializeOwned;
 use std::collections::VecDeque;
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
 use winit::event::{ElementState, WindowEvent};
 use winit::window::Window;
 
@@ -26,6 +31,38 @@ pub struct GameSystem {
     text_render: TextRenderContext,
     pub io: IO,
     dialogue: DialogueSystem,
+    // the base language, then each translation of it
+    languages: Vec<String>,
+    tables: Vec<StringTable>,
+    // index into languages
+    lang: usize,
+    #[cfg(feature = "debug-reload")]
+    reloads: crossbeam_channel::Receiver<(Story, Vec<StringTable>)>,
+}
+
+// compiled by the build script, see build.rs
+const DIALOGUE: &str = "game/dialogue";
+// F5 saves here, F9 loads it
+const SAVE: &str = "save.bincode";
+
+fn load<T: DeserializeOwned>(path: &Path) -> T {
+    let mut file = File::open(path).unwrap();
+    let mut buffer = vec![];
+    file.read_to_end(&mut buffer).expect("failed to read");
+    ir::bundle::decode(&buffer)
+        .unwrap_or_else(|e| panic!("couldn't load {}: {}", path.display(), e))
+}
+
+// every dialogue/<lang>/strings.bincode, sorted by language
+fn load_tables() -> Vec<StringTable> {
+    let mut tables: Vec<StringTable> = std::fs::read_dir(DIALOGUE)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().join("strings.bincode"))
+        .filter(|path| path.is_file())
+        .map(|path| load(&path))
+        .collect();
+    tables.sort_by(|a, b| a.lang.cmp(&b.lang));
+    tables
 }
 
 impl GameSystem {
@@ -34,15 +71,32 @@ impl GameSystem {
         let bg_render = BgRenderContext::build(&gc);
         let text_render = TextRenderContext::build(&gc);
 
-        let controls = Controls::default();
+        let mut controls = Controls::default();
         let audio_tx = AudioSystem::start();
 
         // todo: stop being so lazy!
-        let mut file = File::open("game/dialogue/en/intro.bincode").unwrap();
-        let mut buffer = vec![];
-        file.read_to_end(&mut buffer).expect("failed to read");
-        let chapter: Option<ir::ast::Chapter> = bincode::deserialize(&buffer[..]).unwrap();
-        let dialogue = DialogueSystem::init(chapter.unwrap());
+        let story: Story = load(&Path::new(DIALOGUE).join("en").join("story.bincode"));
+        let tables = load_tables();
+        let mut languages = vec![story.lang.clone()];
+        languages.extend(tables.iter().map(|t| t.lang.clone()));
+
+        // e.g. VOID_LANG=fr, otherwise the story's own language. F2 switches
+        let lang = std::env::var("VOID_LANG")
+            .ok()
+            .and_then(|lang| languages.iter().position(|l| *l == lang))
+            .unwrap_or(0);
+        controls.lang = lang;
+        let strings = lang.checked_sub(1).map(|i| tables[i].clone());
+
+        // e.g. VOID_SEED=42 to get the same <random> picks every time, otherwise it's the clock's
+        let seed = std::env::var("VOID_SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(|| {
+                let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+                now.map_or(0, |d| d.as_nanos() as u64)
+            });
+        let dialogue = DialogueSystem::init(story, strings, seed);
 
         let io = IO {
             ticks: 0,
@@ -57,6 +111,47 @@ impl GameSystem {
             text_render,
             io,
             dialogue,
+            languages,
+            tables,
+            lang,
+            #[cfg(feature = "debug-reload")]
+            reloads: ReloadSystem::start("dialogue-src".into()),
+        }
+    }
+
+    // switches to one of self.languages, falling back to the story's own for untranslated lines
+    fn set_language(&mut self, lang: usize) {
+        self.lang = lang;
+        self.io.controls.lang = lang;
+        let strings = lang.checked_sub(1).map(|i| self.tables[i].clone());
+        self.dialogue.set_strings(strings);
+    }
+
+    fn quicksave(&self) {
+        let bytes = ir::bundle::encode(
+            &self.dialogue.save(),
+            concat!("void ", env!("CARGO_PKG_VERSION")),
+        );
+        match std::fs::write(SAVE, bytes) {
+            Ok(()) => println!("saved to {}", SAVE),
+            Err(e) => eprintln!("couldn't save to {}: {}", SAVE, e),
+        }
+    }
+
+    fn quickload(&mut self) {
+        let bytes = match std::fs::read(SAVE) {
+            Ok(bytes) => bytes,
+            Err(e) => return eprintln!("couldn't load {}: {}", SAVE, e),
+        };
+        match ir::bundle::decode::<Save>(&bytes) {
+            Ok(save) => {
+                self.dialogue.load(save);
+                println!("loaded {}", SAVE);
+            }
+            Err(LoadError::Version { .. }) => {
+                eprintln!("{} is from a different version of the game", SAVE)
+            }
+            Err(_) => eprintln!("{} isn't a save, or it's corrupt", SAVE),
         }
     }
 
@@ -84,6 +179,10 @@ impl GameSystem {
                     }
                 }
             },
+            // already shifted/capsed by the os, so it's exactly what the player typed
+            WindowEvent::ReceivedCharacter(ch) => {
+                self.io.controls.input_char.push(*ch);
+            }
             // WindowEvent::ModifiersChanged(_) => {}
             _ => {}
         }
@@ -91,8 +190,36 @@ impl GameSystem {
     }
 
     pub fn update(&mut self) {
+        #[cfg(feature = "debug-reload")]
+        while let Ok((story, tables)) = self.reloads.try_recv() {
+            // stay in the same language, if it's still there
+            let current = self.languages[self.lang].clone();
+            self.languages = vec![story.lang.clone()];
+            self.languages.extend(tables.iter().map(|t| t.lang.clone()));
+            self.tables = tables;
+
+            self.dialogue.reload(story);
+            let lang = self.languages.iter().position(|l| *l == current);
+            self.set_language(lang.unwrap_or(0));
+        }
+
+        let lang = self.io.controls.lang % self.languages.len();
+        if lang != self.lang {
+            println!("language: {}", self.languages[lang]);
+            self.set_language(lang);
+        }
+
+        if std::mem::take(&mut self.io.controls.save) {
+            self.quicksave();
+        }
+        if std::mem::take(&mut self.io.controls.load) {
+            self.quickload();
+        }
+
         self.io.ticks += 1;
         self.dialogue.update(&mut self.io);
+        // typing is only for whatever's listening this tick
+        self.io.controls.input_char.clear();
     }
 
     pub fn draw(&mut self) {
@@ -139,12 +266,16 @@ impl GameSystem {
                 DrawCommand::DrawBg => {
                     self.bg_render.draw(&mut f_ctx);
                 }
-                DrawCommand::DrawChar => {}
+                DrawCommand::DrawChar(glyph) => {
+                    // glyphs are batched, there can be a lot of them
+                    self.text_render.queue(&glyph);
+                }
                 DrawCommand::DrawString(txt) => {
                     self.text_render.draw(&mut f_ctx, txt);
                 }
             }
         }
+        self.text_render.flush(&mut f_ctx);
 
         self.gc.queue.submit(std::iter::once(encoder.finish()));
     }
@@ -1,3 +1,5 @@
 pub mod controls;
 pub mod audio;
 pub mod game;
+#[cfg(feature = "debug-reload")]
+pub mod reload;
@@ -0,0 +1,45 @@
+use crossbeam_channel::Receiver;
+use ir::ast::{Story, StringTable};
+use std::path::PathBuf;
+
+// with the debug-reload feature, the game watches the dialogue source and gets a freshly
+// compiled story and translations whenever it changes. errors are printed, and the game
+// keeps what it has.
+pub struct ReloadSystem;
+
+impl ReloadSystem {
+    pub fn start(src: PathBuf) -> Receiver<(Story, Vec<StringTable>)> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+
+        std::thread::spawn(move || {
+            let mut watcher = ir_parser::Watcher::new(&src);
+
+            loop {
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                if !watcher.changed() {
+                    continue;
+                }
+
+                match ir_parser::parse_all(&src) {
+                    Ok((story, mut tables)) => {
+                        println!("reloading {}", src.display());
+                        // the same builds as build.rs compiles one for
+                        if cfg!(debug_assertions) {
+                            tables.push(ir_parser::pseudo_localize(&story));
+                        }
+                        if tx.send((story, tables)).is_err() {
+                            break; // the game's gone
+                        }
+                    }
+                    Err(errors) => {
+                        for error in &errors {
+                            eprintln!("{}\n", error);
+                        }
+                    }
+                }
+            }
+        });
+
+        rx
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+use ir::ast::ChExpr;
+
+use crate::po::{entries, Numbering, Tag};
+use crate::story::parse_xml;
+use crate::{languages, parse_story, ErrorKind, ParseError};
+
+// how far along each translation is, and what's gone wrong with it.
+// the structure (awaits, choices, gotos..) is the base language's for everyone,
+// so all that can differ is the lines themselves, the markup inside them, and
+// which of a choice's options are translated.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    // none of the chapter's lines are translated
+    MissingChapter(String),
+    MissingLine(String),
+    // translates a line the base language doesn't have, so it's never shown
+    ExtraLine(String),
+    // the base line has changed since it was translated
+    StaleLine(String),
+    // e.g. the base line plays a sound, and the translation doesn't. a choice with only
+    // some of its options translated is one of these too, by its first option's id
+    Mismatch {
+        id: String,
+        what: &'static str,
+        base: usize,
+        found: usize,
+    },
+}
+
+pub struct Report {
+    pub lang: String,
+    pub translated: usize,
+    pub lines: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl Issue {
+    pub fn is_missing(&self) -> bool {
+        matches!(self, Issue::MissingChapter(_) | Issue::MissingLine(_))
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::MissingChapter(id) => write!(f, "chapter {} isn't translated", id),
+            Issue::MissingLine(id) => write!(f, "{} isn't translated", id),
+            Issue::ExtraLine(id) => write!(f, "{} isn't a line in the base language", id),
+            Issue::StaleLine(id) => write!(f, "{} has changed since it was translated", id),
+            Issue::Mismatch {
+                id,
+                what,
+                base,
+                found,
+            } => write!(
+                f,
+                "{} has {} {}, but its translation has {}",
+                id, base, what, found
+            ),
+        }
+    }
+}
+
+// compares every translation in src_dir with the base language
+pub fn check_translations(src_dir: &Path) -> Result<Vec<Report>, Vec<ParseError>> {
+    let (base, langs) = languages(src_dir)?;
+    let base_dir = src_dir.join(base);
+    let story = parse_story(&base_dir)?;
+    let entries = entries(&base_dir, &story);
+    let mut reports = vec![];
+
+    for lang in langs {
+        let path = src_dir.join(&lang).join("strings.xml");
+        let file = path.display().to_string();
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| vec![ParseError::in_file(&file, ErrorKind::Io(e.to_string()))])?;
+        // anything worse than this is parse_strings' to report
+        let doc = parse_xml(&file, &source)?;
+        let translations: BTreeMap<&str, _> = doc
+            .root_element()
+            .children()
+            .filter(|n| n.is_element())
+            .filter_map(|n| Some((n.attribute("id")?, n)))
+            .collect();
+
+        let mut issues = vec![];
+        let mut translated = 0;
+        let mut started = BTreeSet::new();
+
+        for entry in &entries {
+            let node = match translations.get(entry.id.as_str()) {
+                Some(node) => *node,
+                None => {
+                    issues.push(Issue::MissingLine(entry.id.clone()));
+                    continue;
+                }
+            };
+            translated += 1;
+            started.insert(entry.chapter.as_str());
+
+            if matches!(node.attribute("base"), Some(hash) if hash != entry.hash()) {
+                issues.push(Issue::StaleLine(entry.id.clone()));
+            }
+
+            let mut numbering = Numbering::new();
+            numbering.replace(&source, node);
+            let (base, found) = (markup(&entry.tags), markup(&numbering.tags));
+            for (what, base, found) in [
+                ("instructions", base.0, found.0),
+                ("pauses", base.1, found.1),
+            ] {
+                if base != found {
+                    issues.push(Issue::Mismatch {
+                        id: entry.id.clone(),
+                        what,
+                        base,
+                        found,
+                    });
+                }
+            }
+        }
+
+        // a choice shouldn't be half in one language and half in another
+        for chapter in &story.chapters {
+            for expr in &chapter.content {
+                let options = match expr {
+                    ChExpr::Prompt { options } => options,
+                    _ => continue,
+                };
+                let found = options
+                    .iter()
+                    .filter(|option| translations.contains_key(option.id.as_str()))
+                    .count();
+                if found > 0 && found < options.len() {
+                    issues.push(Issue::Mismatch {
+                        id: options[0].id.clone(),
+                        what: "options",
+                        base: options.len(),
+                        found,
+                    });
+                }
+            }
+        }
+
+        // a chapter nobody's started on is one issue, not one per line
+        for chapter in &story.chapters {
+            if !started.contains(chapter.id.as_str()) {
+                issues.retain(|issue| match issue {
+                    Issue::MissingLine(id) => !entries
+                        .iter()
+                        .any(|e| e.id == *id && e.chapter == chapter.id),
+                    _ => true,
+                });
+                issues.push(Issue::MissingChapter(chapter.id.clone()));
+            }
+        }
+
+        for id in translations.keys() {
+            if !entries.iter().any(|e| e.id == *id) {
+                issues.push(Issue::ExtraLine(id.to_string()));
+            }
+        }
+
+        reports.push(Report {
+            lang,
+            translated,
+            lines: entries.len(),
+            issues,
+        });
+    }
+
+    Ok(reports)
+}
+
+// how many instructions and pauses a line has
+fn markup(tags: &[Tag]) -> (usize, usize) {
+    let count = |prefix| {
+        tags.iter()
+            .filter(|tag| matches!(tag, Tag::Empty(raw) if raw.starts_with(prefix)))
+            .count()
+    };
+    (count("<?"), count("<pause"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_translations, Issue};
+    use crate::{assign_ids, export_po, import_po, ErrorKind};
+
+    #[test]
+    fn issues() {
+        let src = std::env::temp_dir().join("void-check");
+        let _ = std::fs::remove_dir_all(&src);
+        for lang in &["en", "fr", "de"] {
+            std::fs::create_dir_all(src.join(lang)).unwrap();
+        }
+        let files = [
+            ("voices.xml", r#"<voices><voice name="universe"/></voices>"#),
+            (
+                "en/story.xml",
+                r#"<story voices="../voices.xml">
+                    <chapter id="intro" src="intro.xml"/>
+                    <chapter id="end" src="end.xml"/>
+                </story>"#,
+            ),
+            (
+                "en/intro.xml",
+                r#"<chapter voice="universe">
+                    <line id="a">A <?play sfx=blip?>sound.</line>
+                    <line id="b">B.</line>
+                    <line id="c">C.</line>
+                    <choice>
+                        <option><text id="yes">Yes.</text></option>
+                        <option><text id="no">No.</text></option>
+                    </choice>
+                </chapter>"#,
+            ),
+            (
+                "en/end.xml",
+                r#"<chapter voice="universe"><line id="d">D.</line></chapter>"#,
+            ),
+            (
+                "fr/strings.xml",
+                r#"<strings>
+                    <line id="a">Un son.</line>
+                    <line id="b" base="00000000">B.</line>
+                    <line id="yes">Oui.</line>
+                    <line id="z">Z.</line>
+                </strings>"#,
+            ),
+            ("de/strings.xml", "<strings/>"),
+        ];
+        for (file, contents) in &files {
+            std::fs::write(src.join(file), contents).unwrap();
+        }
+
+        let reports = check_translations(&src).unwrap();
+        let langs: Vec<_> = reports.iter().map(|r| r.lang.as_str()).collect();
+        assert_eq!(langs, vec!["de", "fr"]);
+
+        let de = &reports[0];
+        assert_eq!(de.translated, 0);
+        assert_eq!(
+            de.issues,
+            vec![
+                Issue::MissingChapter("intro".to_string()),
+                Issue::MissingChapter("end".to_string())
+            ]
+        );
+
+        let fr = &reports[1];
+        assert_eq!((fr.translated, fr.lines), (3, 6));
+        assert_eq!(
+            fr.issues,
+            vec![
+                Issue::Mismatch {
+                    id: "a".to_string(),
+                    what: "instructions",
+                    base: 1,
+                    found: 0
+                },
+                Issue::StaleLine("b".to_string()),
+                Issue::MissingLine("c".to_string()),
+                Issue::MissingLine("no".to_string()),
+                Issue::Mismatch {
+                    id: "yes".to_string(),
+                    what: "options",
+                    base: 2,
+                    found: 1
+                },
+                Issue::MissingChapter("end".to_string()),
+                Issue::ExtraLine("z".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn edited_line_is_stale() {
+        let src = std::env::temp_dir().join("void-check-stale");
+        let _ = std::fs::remove_dir_all(&src);
+        std::fs::create_dir_all(src.join("en")).unwrap();
+        let intro = |text| {
+            format!(
+                r#"<chapter voice="universe"><line>{}</line><line>Bye.</line></chapter>"#,
+                text
+            )
+        };
+        let files = [
+            (
+                "voices.xml",
+                r#"<voices><voice name="universe"/></voices>"#.to_string(),
+            ),
+            (
+                "en/story.xml",
+                r#"<story voices="../voices.xml"><chapter id="intro" src="intro.xml"/></story>"#
+                    .to_string(),
+            ),
+            ("en/intro.xml", intro("Helo.")),
+        ];
+        for (file, contents) in &files {
+            std::fs::write(src.join(file), contents).unwrap();
+        }
+
+        // the lines need ids of their own before they can be translated
+        let e = export_po(&src, "fr").unwrap_err();
+        assert_eq!(e[0].kind, ErrorKind::GeneratedLineId("intro~1".to_string()));
+        assert_eq!(assign_ids(&src).unwrap(), 2);
+
+        let po = export_po(&src, "fr")
+            .unwrap()
+            .replace(
+                "msgid \"Helo.\"\nmsgstr \"\"",
+                "msgid \"Helo.\"\nmsgstr \"Salut.\"",
+            )
+            .replace(
+                "msgid \"Bye.\"\nmsgstr \"\"",
+                "msgid \"Bye.\"\nmsgstr \"Au revoir.\"",
+            );
+        let po_path = src.join("fr.po");
+        std::fs::write(&po_path, po).unwrap();
+        assert_eq!(import_po(&src, "fr", &po_path).unwrap(), 2);
+
+        // fixing the typo keeps the line's id, so its translation is stale rather than lost
+        let stamped = std::fs::read_to_string(src.join("en/intro.xml")).unwrap();
+        std::fs::write(src.join("en/intro.xml"), stamped.replace("Helo.", "Hello.")).unwrap();
+        let reports = check_translations(&src).unwrap();
+        assert_eq!(reports[0].translated, 2);
+        assert_eq!(
+            reports[0].issues,
+            vec![Issue::StaleLine("intro.1".to_string())]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+
+use ir::ast::Type;
+
+// something wrong with a dialogue file, and where it is
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub file: String,
+    // 1-based, or 0 if the error isn't anywhere in particular (e.g. a missing file)
+    pub line: u32,
+    pub col: u32,
+    pub kind: ErrorKind,
+    // the line the error is on, so it can be printed without the source
+    snippet: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    Io(String),
+    Xml(String),
+    // <expected> was wanted, but <found> was there
+    UnexpectedElement {
+        expected: String,
+        found: String,
+    },
+    UnknownElement(String),
+    UnknownProperty(String),
+    UnknownInstruction(String),
+    UnknownRandomMode(String),
+    UnexpectedText,
+    MissingAttribute {
+        element: String,
+        attr: String,
+    },
+    InvalidNumber {
+        element: String,
+        attr: String,
+        value: String,
+    },
+    // attributes that mean the same thing different ways, so only one can be given
+    ConflictingAttributes {
+        element: String,
+        attrs: String,
+    },
+    InvalidColor(String),
+    InvalidExpr {
+        src: String,
+        message: String,
+    },
+    NotALiteral(String),
+    // a { or } in text that isn't part of an {expression}
+    UnmatchedBrace(String),
+    TypeMismatch {
+        var: String,
+        expected: Type,
+        found: Type,
+    },
+    ConditionNotBool(Type),
+    DuplicateVar(String),
+    // a variable declared by more than one chapter, and where the other declarations are
+    VarDeclaredElsewhere {
+        name: String,
+        others: String,
+    },
+    DuplicateLabel(String),
+    DuplicateChapter(String),
+    DuplicateVoice(String),
+    DuplicateLineId(String),
+    // a verb or noun that's in the vocab twice, or as a synonym of two words
+    DuplicateWord(String),
+    DuplicateRoom(String),
+    DuplicateItem(String),
+    // "inventory" and "here" mean something else where rooms are expected
+    ReservedRoom(String),
+    UndefinedLabel(String),
+    UndefinedChapter(String),
+    UndefinedVoice(String),
+    // a translation of a line the base language doesn't have
+    UndefinedLineId(String),
+    // a translation by a generated line id, which moves when lines are added
+    GeneratedLineId(String),
+    UndefinedVerb(String),
+    UndefinedNoun(String),
+    UndefinedRoom(String),
+    UndefinedItem(String),
+    // no room has an exit that way
+    UndefinedExit(String),
+    ElseWithoutIf,
+    EmptyChoice,
+    EmptyRandom,
+    MissingText,
+    NoChapters,
+    // the base language is the one directory with a story.xml
+    NoBaseLanguage,
+    MultipleBaseLanguages(Vec<String>),
+    InvalidPo(String),
+    // a translation's placeholders don't match its line's markup
+    Placeholder {
+        id: String,
+        message: String,
+    },
+}
+
+impl ParseError {
+    // line and col come from roxmltree's TextPos, so they're 1-based and col counts chars
+    pub fn new(file: &str, source: &str, line: u32, col: u32, kind: ErrorKind) -> Self {
+        let snippet = match line {
+            0 => String::new(),
+            n => source.lines().nth(n as usize - 1).unwrap_or("").to_string(),
+        };
+
+        Self {
+            file: file.to_string(),
+            line,
+            col,
+            kind,
+            snippet,
+        }
+    }
+
+    // for errors about a whole file
+    pub fn in_file(file: &str, kind: ErrorKind) -> Self {
+        Self::new(file, "", 0, 0, kind)
+    }
+
+    // at a byte offset into the source
+    pub fn at(file: &str, doc: &roxmltree::Document, pos: usize, kind: ErrorKind) -> Self {
+        let pos = doc.text_pos_at(pos);
+        Self::new(file, doc.input_text(), pos.row, pos.col, kind)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ErrorKind::*;
+        match self {
+            Io(e) => write!(f, "couldn't read file: {}", e),
+            Xml(e) => write!(f, "invalid xml: {}", e),
+            UnexpectedElement { expected, found } => {
+                write!(f, "expected {}, found <{}>", expected, found)
+            }
+            UnknownElement(name) => write!(f, "<{}> isn't a chapter expression", name),
+            UnknownProperty(name) => write!(f, "<{}> isn't a text property", name),
+            UnknownInstruction(pi) => write!(f, "unsupported processing instruction: <?{}?>", pi),
+            UnknownRandomMode(mode) => write!(
+                f,
+                "<random> mode should be random, shuffle or cycle, found {:?}",
+                mode
+            ),
+            UnexpectedText => write!(f, "text has to be inside a <line>"),
+            MissingAttribute { element, attr } => {
+                write!(f, "<{}> needs a {} attribute", element, attr)
+            }
+            InvalidNumber {
+                element,
+                attr,
+                value,
+            } => write!(
+                f,
+                "<{}> {} should be a number, found {:?}",
+                element, attr, value
+            ),
+            ConflictingAttributes { element, attrs } => {
+                write!(f, "<{}> can't have both {}", element, attrs)
+            }
+            InvalidColor(s) => write!(f, "colors should look like #rrggbb, found {:?}", s),
+            InvalidExpr { src, message } => write!(f, "invalid expression {:?}: {}", src, message),
+            NotALiteral(src) => write!(f, "a <var>'s value must be a literal: {}", src),
+            UnmatchedBrace(brace) => write!(
+                f,
+                "unmatched {} in text, use {}{} for a literal brace",
+                brace, brace, brace
+            ),
+            TypeMismatch {
+                var,
+                expected,
+                found,
+            } => write!(f, "{} is {:?}, but was given {:?}", var, expected, found),
+            ConditionNotBool(ty) => write!(f, "condition must be a Bool, found {:?}", ty),
+            DuplicateVar(name) => write!(f, "variable declared more than once: {}", name),
+            VarDeclaredElsewhere { name, others } => {
+                write!(f, "variable {} is also declared at {}", name, others)
+            }
+            DuplicateLabel(name) => write!(f, "label defined more than once: {}", name),
+            DuplicateChapter(id) => write!(f, "chapter id used more than once: {}", id),
+            DuplicateVoice(name) => write!(f, "voice defined more than once: {}", name),
+            DuplicateLineId(id) => write!(f, "line id used more than once: {}", id),
+            DuplicateWord(word) => write!(f, "word defined more than once: {}", word),
+            DuplicateRoom(id) => write!(f, "room defined more than once: {}", id),
+            DuplicateItem(id) => write!(f, "item defined more than once: {}", id),
+            ReservedRoom(id) => write!(f, "{} can't be used as a room id", id),
+            UndefinedLabel(name) => write!(f, "undefined label: {}", name),
+            UndefinedChapter(id) => write!(f, "undefined chapter: {}", id),
+            UndefinedVoice(name) => write!(f, "undefined voice: {}", name),
+            UndefinedLineId(id) => write!(f, "the base language has no line with id: {}", id),
+            GeneratedLineId(id) => write!(
+                f,
+                "{} has no id of its own, so it can't be translated yet (voidc ids gives it one)",
+                id
+            ),
+            UndefinedVerb(verb) => write!(f, "verb isn't in the vocab: {}", verb),
+            UndefinedNoun(noun) => write!(f, "noun isn't in the vocab: {}", noun),
+            UndefinedRoom(id) => write!(f, "undefined room: {}", id),
+            UndefinedItem(id) => write!(f, "undefined item: {}", id),
+            UndefinedExit(dir) => write!(f, "no room has an exit called {}", dir),
+            ElseWithoutIf => write!(f, "<else> must come right after an <if>"),
+            EmptyChoice => write!(f, "a <choice> needs at least one <option>"),
+            EmptyRandom => write!(f, "a <random> needs at least one <variant>"),
+            MissingText => write!(f, "an <option> needs a <text>"),
+            NoChapters => write!(f, "a story needs at least one chapter"),
+            NoBaseLanguage => write!(f, "no language directory has a story.xml"),
+            MultipleBaseLanguages(langs) => write!(
+                f,
+                "only the base language should have a story.xml, found one in: {}",
+                langs.join(", ")
+            ),
+            InvalidPo(e) => write!(f, "invalid po file: {}", e),
+            Placeholder { id, message } => write!(f, "in the translation of {}: {}", id, message),
+        }
+    }
+}
+
+// error: <frobnicate> isn't a chapter expression
+//   --> dialogue-src/en/intro.xml:12:5
+//    |
+// 12 |     <frobnicate/>
+//    |     ^
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "error: {}", self.kind)?;
+        if self.line == 0 {
+            return write!(f, "  --> {}", self.file);
+        }
+
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "{}--> {}:{}:{}", gutter, self.file, self.line, self.col)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.snippet)?;
+
+        // keep tabs, so the caret lines up however wide they are
+        let indent: String = self
+            .snippet
+            .chars()
+            .take(self.col.saturating_sub(1) as usize)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(f, "{} | {}^", gutter, indent)
+    }
+}
+
+impl std::error::Error for ParseError {}
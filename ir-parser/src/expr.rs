@@ -0,0 +1,489 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use ir::ast::{BinOp, Expr, Query, Type, UnOp, Value, World};
+
+// expressions live in attributes, e.g. <if cond="trust >= 3 && !met"/>
+//
+// or      := and ("||" and)*
+// and     := cmp ("&&" cmp)*
+// cmp     := sum (("==" | "!=" | "<" | "<=" | ">" | ">=") sum)?
+// sum     := product (("+" | "-") product)*
+// product := unary (("*" | "/" | "%") unary)*
+// unary   := ("!" | "-") unary | primary
+// primary := int | "true" | "false" | 'string' | ident | query | "(" or ")"
+// query   := "here" "(" ")" | ("has" | "location" | "state" | "exit") "(" 'string' ")"
+//
+// strings can use either quote, so they can sit inside either kind of attribute.
+// `+` also joins strings, and `==`/`!=` compare any two values of the same type.
+// queries ask about the story's world (see world.rs), and their arguments have to be
+// literals, so they can be checked against it.
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExprError {
+    // byte offset into the expression
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at offset {})", self.message, self.offset)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Int(i64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+// longest first, so "<=" isn't read as "<"
+const OPS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "!",
+];
+
+fn tokenize(src: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let mut tokens = vec![];
+    let mut chars = src.char_indices().peekable();
+
+    while let Some(&(pos, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            chars.next();
+            tokens.push((
+                pos,
+                if c == '(' {
+                    Token::LParen
+                } else {
+                    Token::RParen
+                },
+            ));
+        } else if c.is_ascii_digit() {
+            let mut end = pos;
+            while let Some(&(i, c)) = chars.peek() {
+                if !c.is_ascii_digit() {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let n = src[pos..end].parse().map_err(|_| ExprError {
+                offset: pos,
+                message: format!("integer is too large: {}", &src[pos..end]),
+            })?;
+            tokens.push((pos, Token::Int(n)));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = pos;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push((pos, Token::Ident(src[pos..end].to_string())));
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, q)) if q == c => break,
+                    Some((_, ch)) => s.push(ch),
+                    None => {
+                        return Err(ExprError {
+                            offset: pos,
+                            message: "unterminated string".to_string(),
+                        })
+                    }
+                }
+            }
+            tokens.push((pos, Token::Str(s)));
+        } else if let Some(op) = OPS.iter().find(|op| src[pos..].starts_with(*op)) {
+            for _ in 0..op.len() {
+                chars.next();
+            }
+            tokens.push((pos, Token::Op(op)));
+        } else {
+            return Err(ExprError {
+                offset: pos,
+                message: format!("unexpected character: {:?}", c),
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct ExprParser<'a> {
+    tokens: Vec<(usize, Token)>,
+    idx: usize,
+    end: usize,
+    types: &'a BTreeMap<String, Type>,
+    world: &'a World,
+}
+
+impl<'a> ExprParser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.idx).map(|(_, t)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.idx).map_or(self.end, |(pos, _)| *pos)
+    }
+
+    fn error<T>(&self, offset: usize, message: String) -> Result<T, ExprError> {
+        Err(ExprError { offset, message })
+    }
+
+    // consumes the next token if it's one of `ops`
+    fn eat_op(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+        if let Some(Token::Op(op)) = self.peek() {
+            if let Some(op) = ops.iter().find(|o| *o == op) {
+                self.idx += 1;
+                return Some(op);
+            }
+        }
+        None
+    }
+
+    fn expect(&self, found: Type, expected: Type, offset: usize) -> Result<(), ExprError> {
+        if found == expected {
+            Ok(())
+        } else {
+            self.error(
+                offset,
+                format!("expected {:?}, found {:?}", expected, found),
+            )
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<(Expr, Type), ExprError> {
+        self.parse_logical("||", BinOp::Or, Self::parse_and)
+    }
+
+    fn parse_and(&mut self) -> Result<(Expr, Type), ExprError> {
+        self.parse_logical("&&", BinOp::And, Self::parse_cmp)
+    }
+
+    fn parse_logical(
+        &mut self,
+        token: &'static str,
+        op: BinOp,
+        next: fn(&mut Self) -> Result<(Expr, Type), ExprError>,
+    ) -> Result<(Expr, Type), ExprError> {
+        let offset = self.offset();
+        let (mut lhs, ty) = next(self)?;
+
+        while self.eat_op(&[token]).is_some() {
+            self.expect(ty, Type::Bool, offset)?;
+            let offset = self.offset();
+            let (rhs, rty) = next(self)?;
+            self.expect(rty, Type::Bool, offset)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok((lhs, ty))
+    }
+
+    fn parse_cmp(&mut self) -> Result<(Expr, Type), ExprError> {
+        let offset = self.offset();
+        let (lhs, lty) = self.parse_sum()?;
+
+        let op = match self.eat_op(&["==", "!=", "<", "<=", ">", ">="]) {
+            Some("==") => BinOp::Eq,
+            Some("!=") => BinOp::Ne,
+            Some("<") => BinOp::Lt,
+            Some("<=") => BinOp::Le,
+            Some(">") => BinOp::Gt,
+            Some(">=") => BinOp::Ge,
+            _ => return Ok((lhs, lty)),
+        };
+
+        let (rhs, rty) = self.parse_sum()?;
+        match op {
+            BinOp::Eq | BinOp::Ne => {
+                if lty != rty {
+                    return self.error(offset, format!("can't compare {:?} with {:?}", lty, rty));
+                }
+            }
+            _ => {
+                self.expect(lty, Type::Int, offset)?;
+                self.expect(rty, Type::Int, offset)?;
+            }
+        }
+
+        Ok((Expr::Binary(op, Box::new(lhs), Box::new(rhs)), Type::Bool))
+    }
+
+    fn parse_sum(&mut self) -> Result<(Expr, Type), ExprError> {
+        let offset = self.offset();
+        let (mut lhs, ty) = self.parse_product()?;
+
+        while let Some(op) = self.eat_op(&["+", "-"]) {
+            let (rhs, rty) = self.parse_product()?;
+            let op = if op == "+" { BinOp::Add } else { BinOp::Sub };
+
+            match (op, ty, rty) {
+                (_, Type::Int, Type::Int) | (BinOp::Add, Type::Str, Type::Str) => {}
+                _ => {
+                    return self.error(
+                        offset,
+                        format!("can't apply {:?} to {:?} and {:?}", op, ty, rty),
+                    )
+                }
+            }
+
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok((lhs, ty))
+    }
+
+    fn parse_product(&mut self) -> Result<(Expr, Type), ExprError> {
+        let offset = self.offset();
+        let (mut lhs, ty) = self.parse_unary()?;
+
+        while let Some(op) = self.eat_op(&["*", "/", "%"]) {
+            self.expect(ty, Type::Int, offset)?;
+            let offset = self.offset();
+            let (rhs, rty) = self.parse_unary()?;
+            self.expect(rty, Type::Int, offset)?;
+
+            let op = match op {
+                "*" => BinOp::Mul,
+                "/" => BinOp::Div,
+                _ => BinOp::Rem,
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok((lhs, ty))
+    }
+
+    fn parse_unary(&mut self) -> Result<(Expr, Type), ExprError> {
+        let offset = self.offset();
+
+        if let Some(op) = self.eat_op(&["!", "-"]) {
+            let (expr, ty) = self.parse_unary()?;
+            let (op, expected) = if op == "!" {
+                (UnOp::Not, Type::Bool)
+            } else {
+                (UnOp::Neg, Type::Int)
+            };
+            self.expect(ty, expected, offset)?;
+            return Ok((Expr::Unary(op, Box::new(expr)), ty));
+        }
+
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<(Expr, Type), ExprError> {
+        let offset = self.offset();
+        let token = match self.peek() {
+            Some(t) => t.clone(),
+            None => return self.error(offset, "unexpected end of expression".to_string()),
+        };
+        self.idx += 1;
+
+        match token {
+            Token::Int(n) => Ok((Expr::Lit(Value::Int(n)), Type::Int)),
+            Token::Str(s) => Ok((Expr::Lit(Value::Str(s)), Type::Str)),
+            Token::Ident(name) if self.peek() == Some(&Token::LParen) => {
+                self.parse_query(&name, offset)
+            }
+            Token::Ident(name) => match name.as_str() {
+                "true" => Ok((Expr::Lit(Value::Bool(true)), Type::Bool)),
+                "false" => Ok((Expr::Lit(Value::Bool(false)), Type::Bool)),
+                _ => match self.types.get(&name) {
+                    Some(ty) => Ok((Expr::Var(name, *ty), *ty)),
+                    None => self.error(offset, format!("unknown variable: {}", name)),
+                },
+            },
+            Token::LParen => {
+                let inner = self.parse_or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return self.error(self.offset(), "expected `)`".to_string());
+                }
+                self.idx += 1;
+                Ok(inner)
+            }
+            t => self.error(offset, format!("unexpected {:?}", t)),
+        }
+    }
+
+    // the name's been parsed, and the "(" is next
+    fn parse_query(&mut self, name: &str, offset: usize) -> Result<(Expr, Type), ExprError> {
+        self.idx += 1;
+        let arg_offset = self.offset();
+        let arg = match self.peek() {
+            Some(Token::Str(arg)) if name != "here" => {
+                let arg = arg.clone();
+                self.idx += 1;
+                Some(arg)
+            }
+            _ => None,
+        };
+        if self.peek() != Some(&Token::RParen) {
+            return self.error(self.offset(), "expected `)`".to_string());
+        }
+        self.idx += 1;
+
+        let item = |arg: Option<String>| match arg {
+            Some(item) if self.world.items.contains_key(&item) => Ok(item),
+            Some(item) => self.error(arg_offset, format!("unknown item: {}", item)),
+            None => self.error(arg_offset, format!("{}() needs an item", name)),
+        };
+        let query = match name {
+            "here" => Query::Here,
+            "has" => Query::Has(item(arg)?),
+            "location" => Query::Location(item(arg)?),
+            "state" => Query::State(item(arg)?),
+            "exit" => match arg {
+                Some(dir)
+                    if self
+                        .world
+                        .rooms
+                        .values()
+                        .any(|r| r.exits.contains_key(&dir)) =>
+                {
+                    Query::Exit(dir)
+                }
+                Some(dir) => return self.error(arg_offset, format!("unknown exit: {}", dir)),
+                None => return self.error(arg_offset, "exit() needs a direction".to_string()),
+            },
+            _ => return self.error(offset, format!("unknown function: {}", name)),
+        };
+
+        let ty = query.ty();
+        Ok((Expr::Query(query), ty))
+    }
+}
+
+// parses and type checks an expression, given the types of the variables in scope
+pub fn parse(
+    src: &str,
+    types: &BTreeMap<String, Type>,
+    world: &World,
+) -> Result<(Expr, Type), ExprError> {
+    let mut parser = ExprParser {
+        tokens: tokenize(src)?,
+        idx: 0,
+        end: src.len(),
+        types,
+        world,
+    };
+
+    let expr = parser.parse_or()?;
+
+    if parser.peek().is_some() {
+        return parser.error(parser.offset(), "unexpected trailing input".to_string());
+    }
+
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use ir::ast::{BinOp, Expr, Item, Query, Room, Type, UnOp, Value, World};
+    use std::collections::BTreeMap;
+
+    fn parse(src: &str, types: &BTreeMap<String, Type>) -> Result<(Expr, Type), super::ExprError> {
+        let mut world = World::default();
+        let mut cell = Room::default();
+        cell.exits.insert("north".to_string(), "cell".to_string());
+        world.rooms.insert("cell".to_string(), cell);
+        world.items.insert(
+            "key".to_string(),
+            Item {
+                location: "cell".to_string(),
+                state: String::new(),
+            },
+        );
+        super::parse(src, types, &world)
+    }
+
+    fn types() -> BTreeMap<String, Type> {
+        let mut types = BTreeMap::new();
+        types.insert("trust".to_string(), Type::Int);
+        types.insert("met".to_string(), Type::Bool);
+        types.insert("name".to_string(), Type::Str);
+        types
+    }
+
+    #[test]
+    fn precedence() {
+        let (expr, ty) = parse("!met || trust + 1 * 2 >= 3", &types()).unwrap();
+        assert_eq!(ty, Type::Bool);
+
+        let trust = Expr::Var("trust".to_string(), Type::Int);
+        let int = |n| Box::new(Expr::Lit(Value::Int(n)));
+        let sum = Expr::Binary(
+            BinOp::Add,
+            Box::new(trust),
+            Box::new(Expr::Binary(BinOp::Mul, int(1), int(2))),
+        );
+
+        assert_eq!(
+            expr,
+            Expr::Binary(
+                BinOp::Or,
+                Box::new(Expr::Unary(
+                    UnOp::Not,
+                    Box::new(Expr::Var("met".to_string(), Type::Bool))
+                )),
+                Box::new(Expr::Binary(BinOp::Ge, Box::new(sum), int(3))),
+            )
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let (_, ty) = parse("name == 'Devin' && name + \"!\" != ''", &types()).unwrap();
+        assert_eq!(ty, Type::Bool);
+    }
+
+    #[test]
+    fn type_errors() {
+        let types = types();
+        assert_eq!(parse("trust && met", &types).unwrap_err().offset, 0);
+        assert!(parse("name < 3", &types).is_err());
+        assert!(parse("name == 3", &types).is_err());
+        assert!(parse("-met", &types).is_err());
+        assert!(parse("stranger", &types).is_err());
+        assert!(parse("(trust", &types).is_err());
+        assert!(parse("trust 3", &types).is_err());
+    }
+
+    #[test]
+    fn queries() {
+        let types = types();
+        let (expr, ty) = parse("has('key') && here() == 'cell'", &types).unwrap();
+        assert_eq!(ty, Type::Bool);
+        assert_eq!(
+            expr,
+            Expr::Binary(
+                BinOp::And,
+                Box::new(Expr::Query(Query::Has("key".to_string()))),
+                Box::new(Expr::Binary(
+                    BinOp::Eq,
+                    Box::new(Expr::Query(Query::Here)),
+                    Box::new(Expr::Lit(Value::Str("cell".to_string())))
+                ))
+            )
+        );
+        assert_eq!(parse("exit('north')", &types).unwrap().1, Type::Str);
+
+        assert_eq!(parse("state('lamp')", &types).unwrap_err().offset, 6);
+        assert!(parse("exit('up')", &types).is_err());
+        assert!(parse("has()", &types).is_err());
+        assert!(parse("here('cell')", &types).is_err());
+        assert!(parse("frob('key')", &types).is_err());
+        assert!(parse("location('key' == 'x'", &types).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::path::Path;
+
+use roxmltree::Node;
+
+use crate::story::parse_xml;
+use crate::{languages, parse_story, ErrorKind, ParseError};
+
+// a line without an id="" gets a generated one, like intro~3, from where it is among the
+// chapter's other unnamed lines. that moves whenever a line's added above it, so translations
+// can't use it. before translating, voidc ids writes an id into every line that doesn't
+// have one: the chapter's id and a number after any it's already used, e.g. <line id="intro.4">.
+// those are part of the source from then on, so adding lines never changes them.
+
+// returns how many lines were given ids
+pub fn assign_ids(src_dir: &Path) -> Result<usize, Vec<ParseError>> {
+    let (base, _) = languages(src_dir)?;
+    let base_dir = src_dir.join(base);
+    // nothing's written unless the whole story parses
+    let story = parse_story(&base_dir)?;
+    let mut assigned = 0;
+
+    for chapter in &story.chapters {
+        // parse_story always makes source maps, they're only left out of compiled stories
+        let path = match &chapter.source {
+            Some(source) => base_dir.join(&source.file),
+            None => continue,
+        };
+        let file = path.display().to_string();
+        let io_error =
+            |e: std::io::Error| vec![ParseError::in_file(&file, ErrorKind::Io(e.to_string()))];
+
+        let source = std::fs::read_to_string(&path).map_err(io_error)?;
+        let (stamped, n) = stamp(&chapter.id, &file, &source)?;
+        if n > 0 {
+            std::fs::write(&path, stamped).map_err(io_error)?;
+            assigned += n;
+        }
+    }
+
+    Ok(assigned)
+}
+
+// gives the lines in a chapter's source that haven't got an id the next free ones
+fn stamp(chapter: &str, file: &str, source: &str) -> Result<(String, usize), Vec<ParseError>> {
+    let doc = parse_xml(file, source)?;
+    let lines: Vec<Node> = doc.descendants().filter(is_line).collect();
+
+    let prefix = format!("{}.", chapter);
+    let last = lines
+        .iter()
+        .filter_map(|node| {
+            node.attribute("id")?
+                .strip_prefix(&prefix)?
+                .parse::<u32>()
+                .ok()
+        })
+        .max()
+        .unwrap_or(0);
+    let unnamed: Vec<Node> = lines
+        .into_iter()
+        .filter(|node| node.attribute("id").is_none())
+        .collect();
+
+    let mut stamped = source.to_string();
+    // from the end, so the positions before each one stay where they were
+    for (n, node) in unnamed.iter().enumerate().rev() {
+        // right after the tag's name, e.g. <line| voice="universe">
+        let at = node.range().start + 1 + node.tag_name().name().len();
+        stamped.insert_str(at, &format!(" id=\"{}{}\"", prefix, last + 1 + n as u32));
+    }
+
+    Ok((stamped, unnamed.len()))
+}
+
+// the elements ChapterParser::line_id gives ids to
+fn is_line(node: &Node) -> bool {
+    match node.tag_name().name() {
+        "line" => true,
+        "text" => matches!(node.parent_element(), Some(parent) if parent.has_tag_name("option")),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assign_ids, stamp};
+    use crate::parse_story;
+
+    #[test]
+    fn stamped_ids_stay_put() {
+        let source = r#"<chapter voice="universe">
+    <line>One.</line>
+    <line id="intro.7">Two.</line>
+    <choice><option><text>Three.</text></option></choice>
+    <line id="hello">Four.</line>
+</chapter>"#;
+        let (stamped, n) = stamp("intro", "intro.xml", source).unwrap();
+        assert_eq!(n, 2);
+        assert!(stamped.contains(r#"<line id="intro.8">One.</line>"#));
+        assert!(stamped.contains(r#"<text id="intro.9">Three.</text>"#));
+
+        // a new line at the top takes the next number, and nothing else moves
+        let inserted = stamped.replace(
+            "<line id=\"intro.8\">",
+            "<line>Zero.</line>\n    <line id=\"intro.8\">",
+        );
+        let (restamped, n) = stamp("intro", "intro.xml", &inserted).unwrap();
+        assert_eq!(n, 1);
+        assert_eq!(
+            restamped,
+            stamped.replace(
+                "<line id=\"intro.8\">",
+                "<line id=\"intro.10\">Zero.</line>\n    <line id=\"intro.8\">"
+            )
+        );
+
+        // and once they're all named, it's left alone
+        assert_eq!(
+            stamp("intro", "intro.xml", &restamped).unwrap(),
+            (restamped.clone(), 0)
+        );
+    }
+
+    #[test]
+    fn inserted_lines_keep_ids() {
+        let dir = std::env::temp_dir().join("void-ids");
+        let en = dir.join("en");
+        // whatever an earlier run left behind
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&en).unwrap();
+        std::fs::write(
+            dir.join("voices.xml"),
+            r#"<voices><voice name="universe"/></voices>"#,
+        )
+        .unwrap();
+        std::fs::write(
+            en.join("story.xml"),
+            r#"<story voices="../voices.xml"><chapter id="intro" src="intro.xml"/></story>"#,
+        )
+        .unwrap();
+        let chapter = |lines: &[&str]| {
+            let lines: String = lines
+                .iter()
+                .map(|l| format!("<line>{}</line>", l))
+                .collect();
+            format!(r#"<chapter voice="universe">{}</chapter>"#, lines)
+        };
+        let ids = || -> Vec<String> {
+            let story = parse_story(&en).unwrap();
+            story.lines().map(|(id, _)| id.to_string()).collect()
+        };
+
+        std::fs::write(en.join("intro.xml"), chapter(&["A", "B"])).unwrap();
+        // until they're stamped, lines only have generated ids
+        assert_eq!(ids(), ["intro~1", "intro~2"]);
+        assert_eq!(assign_ids(&dir).unwrap(), 2);
+        assert_eq!(ids(), ["intro.1", "intro.2"]);
+
+        let stamped = std::fs::read_to_string(en.join("intro.xml")).unwrap();
+        std::fs::write(
+            en.join("intro.xml"),
+            stamped.replacen("<line", "<line>new</line><line", 1),
+        )
+        .unwrap();
+        // which would've moved, had those lines not been given their own
+        assert_eq!(ids(), ["intro~1", "intro.1", "intro.2"]);
+        assert_eq!(assign_ids(&dir).unwrap(), 1);
+        assert_eq!(ids(), ["intro.3", "intro.1", "intro.2"]);
+    }
+}
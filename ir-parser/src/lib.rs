@@ -2,20 +2,45 @@
 #![allow(unused_variables)]
 #![allow(dead_code)]
 
+use std::collections::BTreeMap;
 use std::fs::File;
+use std::ops::Range;
 use std::path::Path;
 use std::rc::Rc;
 
-use roxmltree;
 use roxmltree::{ExpandedName, Node, NodeType};
 
 use ir::ast;
 use ir::ast::Action::Await;
 use ir::ast::ChExpr::{Action, Line};
 use ir::ast::Instruction::Play;
-use ir::ast::{ChExpr, Chapter, Instruction, LineChild, Props, Span, TextProperties};
+use ir::ast::{
+    ChExpr, Chapter, CommandHandler, Destination, Effect, Expr, Instruction, LineChild, Loc, Place,
+    PromptOption, Props, RandomMode, SourceMap, Span, Story, StringTable, TextProperties, Type,
+    Value, Variant, World,
+};
 use std::io::{Read, Write};
 
+mod check;
+mod error;
+mod expr;
+mod ids;
+mod po;
+mod pseudo;
+mod story;
+mod strings;
+mod watch;
+mod world;
+
+pub use check::{check_translations, Issue, Report};
+pub use error::{ErrorKind, ParseError};
+pub use ids::assign_ids;
+pub use po::{export_po, import_po};
+pub use pseudo::{pseudo_localize, PSEUDO};
+pub use story::parse_story;
+pub use strings::parse_strings;
+pub use watch::Watcher;
+
 // 🦆
 // the idea of the DialogueIntermediate is that I want to store
 // an ordered list of Expressions which represent text or control flow.
@@ -23,100 +48,575 @@ use std::io::{Read, Write};
 // A chapter expression must be either:
 // - a line
 // - an await
+// - a wait (for some time, rather than for the player)
 // - [an instruction]
-// - [a prompt]
-// - [a jump/goto?]
+// - a prompt (a list of options, each with its own chapter expressions)
+// - a jump (within the chapter, or to another one)
+// - a call (<call label=..>, a jump that comes back to what's next at the following <return/>)
+// - setting a variable
+// - a branch (an if, and maybe an else)
+// - an input (<input var=name max=12/>, the player typing into a string variable)
+// - a command (<commands>, the player typing e.g. "take key", which is matched
+//   against <on verb=take noun=key label=..> handlers, using the story's vocab)
+// - a change to the world (<go>, <move> or <state>, see world.rs)
+// - a random pick (<random mode=..>, one of its <variant weight=..>s, see parse_random)
+//
+// Conditions and values are expressions (see expr.rs), and are type checked here.
+// A variable's type comes from its <var> declaration, or from the first literal <set> of it
+// (or an <input>, which is always a string).
+//
+// Chapters are flat: a prompt's options are lowered to jumps into their content,
+// and each option's content jumps past the rest when it's done.
+// Labels don't produce an expression, they just name the index of whatever comes next.
 //
 // Text is generally/always represented as lines.
 // A line is an ordered list of partial phrases.
 // A partial phrase is:
-// - text with some properties, or
-// - [an instruction (e.g., play a sound, shake the screen - things of that nature)]
+// - text with some properties,
+// - an {expression} in the text, e.g. "It's you, {name}." ({{ and }} are literal braces),
+// - a pause, or
+// - an instruction (<?play song=..?>, <?play sfx=..?> or <?stop?>), run when the line reaches it
 //
 // Text properties can be:
 // - speed,
+// - voice (the chapter's, unless a <line voice=..> or <voice name=..> says otherwise),
+// - effects (<wiggle>, <wave>, <shake>, <rainbow> and <fade>, which can be nested),
 // - [etc]
 //
 
 // ChapterParser reads an xml file and turns it into the appropriate
+// chapter, collecting every error it finds along the way rather than stopping at the first.
+
+// the base language is the directory in src_dir with a story.xml,
+// and every directory with a strings.xml is a translation of it
+pub fn languages(src_dir: &Path) -> Result<(String, Vec<String>), Vec<ParseError>> {
+    let dir = src_dir.display().to_string();
+    let io_error =
+        |e: std::io::Error| vec![ParseError::in_file(&dir, ErrorKind::Io(e.to_string()))];
+
+    let mut bases = vec![];
+    let mut translations = vec![];
+    for entry in std::fs::read_dir(src_dir).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        let lang = || path.file_name().unwrap().to_string_lossy().into_owned();
+        if path.join("story.xml").is_file() {
+            bases.push(lang());
+        } else if path.join("strings.xml").is_file() {
+            translations.push(lang());
+        }
+    }
+    // so errors come out in the same order every time
+    bases.sort();
+    translations.sort();
+
+    match bases.len() {
+        0 => Err(vec![ParseError::in_file(&dir, ErrorKind::NoBaseLanguage)]),
+        1 => Ok((bases.remove(0), translations)),
+        _ => Err(vec![ParseError::in_file(
+            &dir,
+            ErrorKind::MultipleBaseLanguages(bases),
+        )]),
+    }
+}
+
+// parses the story and every translation of it without writing anything
+pub fn parse_all(src_dir: &Path) -> Result<(Story, Vec<StringTable>), Vec<ParseError>> {
+    let (base, langs) = languages(src_dir)?;
+    // translations can't be checked without the story's lines
+    let story = parse_story(&src_dir.join(base))?;
+    let mut tables = vec![];
+    let mut errors = vec![];
+
+    for lang in langs {
+        match parse_strings(&src_dir.join(&lang), &story) {
+            Ok(table) => tables.push(table),
+            Err(mut e) => errors.append(&mut e),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok((story, tables))
+    } else {
+        Err(errors)
+    }
+}
+
+pub struct CompileOptions {
+    // keep where every expression came from, for the game's debug overlay
+    pub source_maps: bool,
+    // also write a pseudo-localized language, for testing layout (see pseudo.rs)
+    pub pseudo: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            source_maps: true,
+            pseudo: true,
+        }
+    }
+}
+
+// compiles the base language to out_dir/<lang>/story.bincode, and every translation to
+// out_dir/<lang>/strings.bincode, returning the languages with the base first.
+// nothing is written unless everything compiles, and then whatever an earlier run wrote
+// for a language that's gone (or isn't wanted, like pseudo in release) is removed
+pub fn compile_all(
+    src_dir: &Path,
+    out_dir: &Path,
+    options: &CompileOptions,
+) -> Result<Vec<String>, Vec<ParseError>> {
+    let (mut story, mut tables) = parse_all(src_dir)?;
+    let mut langs = vec![story.lang.clone()];
+    if options.pseudo {
+        tables.push(pseudo_localize(&story));
+    }
+
+    let out_path = out_dir.join(&story.lang).join("story.bincode");
+    write_story(&mut story, &out_path, options)?;
+    for table in tables {
+        let out_path = out_dir.join(&table.lang).join("strings.bincode");
+        write_bundle(&ir::bundle::encode(&table, COMPILER), &out_path)?;
+        langs.push(table.lang);
+    }
+    remove_stale(out_dir, &langs)?;
+    Ok(langs)
+}
+
+// only our own files go, in case out_dir has anything else in it
+fn remove_stale(out_dir: &Path, langs: &[String]) -> Result<(), Vec<ParseError>> {
+    let io_error = |path: &Path, e: std::io::Error| {
+        let file = path.display().to_string();
+        vec![ParseError::in_file(&file, ErrorKind::Io(e.to_string()))]
+    };
+    let entries = std::fs::read_dir(out_dir).map_err(|e| io_error(out_dir, e))?;
+
+    for entry in entries {
+        let dir = entry.map_err(|e| io_error(out_dir, e))?.path();
+        let lang = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let wanted = match &lang {
+            Some(lang) if *lang == langs[0] => "story.bincode",
+            Some(lang) if langs.contains(lang) => "strings.bincode",
+            _ => "",
+        };
+        for file in &["story.bincode", "strings.bincode"] {
+            let path = dir.join(file);
+            if *file != wanted && path.is_file() {
+                std::fs::remove_file(&path).map_err(|e| io_error(&path, e))?;
+            }
+        }
+        // it's fine if there's something else in there
+        let _ = std::fs::remove_dir(&dir);
+    }
+    Ok(())
+}
+
+// in_path is a language directory, with a story.xml manifest of its chapters
+pub fn compile_ir(
+    in_path: &Path,
+    out_path: &Path,
+    options: &CompileOptions,
+) -> Result<(), Vec<ParseError>> {
+    let mut story = parse_story(in_path)?;
+    write_story(&mut story, out_path, options)
+}
+
+fn write_story(
+    story: &mut Story,
+    out_path: &Path,
+    options: &CompileOptions,
+) -> Result<(), Vec<ParseError>> {
+    if !options.source_maps {
+        for chapter in &mut story.chapters {
+            chapter.source = None;
+        }
+    }
+    write_bundle(&ir::bundle::encode(story, COMPILER), out_path)
+}
+
+fn write_bundle(encoded: &[u8], out_path: &Path) -> Result<(), Vec<ParseError>> {
+    let write = || {
+        if let Some(dir) = out_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        File::create(out_path)?.write_all(encoded)
+    };
+    write().map_err(|e| {
+        vec![ParseError::in_file(
+            &out_path.display().to_string(),
+            ErrorKind::Io(e.to_string()),
+        )]
+    })
+}
 
-// TODO: make voice a TextProperty on every span
+// recorded in compiled stories, to help explain version mismatches
+const COMPILER: &str = concat!("ir-parser ", env!("CARGO_PKG_VERSION"));
 
-pub fn compile_ir(in_path: &Path, mut chapter: File, out_path: &Path) {
-    // in_path might be used for relative file locations
-    let mut s = String::new();
-    let _ = chapter.read_to_string(&mut s);
+// the game ticks once a frame, and vsync keeps it at about this rate
+const TICKS_PER_SECOND: u64 = 60;
 
-    let parser = ChapterParser::from(s.as_str());
-    let encoded = bincode::serialize(&parser.chapter).unwrap();
+// between the chapter's id and the number of a line without an id="", e.g. intro~3
+const GENERATED: char = '~';
 
-    // TODO: fix
-    let mut file = File::create(Path::new("../game/dialogue/en/intro.bincode")).unwrap();
-    file.write_all(&encoded).expect("couldn't write file???");
+// whether a line's id is one line_id made up for it
+pub(crate) fn is_generated(id: &str) -> bool {
+    id.contains(GENERATED)
 }
 
 pub struct ChapterParser<'a> {
-    doc: Rc<roxmltree::Document<'a>>,
+    // None if the xml couldn't be parsed at all
+    doc: Option<Rc<roxmltree::Document<'a>>>,
+    file: String,
     chapter: Option<ast::Chapter>,
+    errors: Vec<ParseError>,
     expr_stack: Option<Vec<ast::ChExpr>>,
     prop_stack: Vec<ast::Props>,
+    // the chapter's voice, for spans that don't have their own
+    voice: String,
+    labels: BTreeMap<String, usize>,
+    // jumps which are waiting for their label to be resolved, and where the goto was
+    gotos: Vec<(usize, String, usize)>,
+    // same for command handlers: the command, which of its handlers, the label and where
+    handler_labels: Vec<(usize, usize, String, usize)>,
+    // labels called from this chapter, and where, which only have to exist
+    calls: Vec<(String, usize)>,
+    vars: BTreeMap<String, Value>,
+    types: BTreeMap<String, Type>,
+    // for the chapter's source map, by expression index
+    locs: BTreeMap<usize, Loc>,
+    children: BTreeMap<usize, Vec<Loc>>,
+    // the children of the line being parsed
+    line_locs: Vec<Loc>,
+    // the chapter's id, which generated line ids start with
+    id: String,
+    // how many times each line id has come up, so repeated lines get their own
+    line_ids: BTreeMap<String, usize>,
+    // how many lines without an id there have been, which numbers the next one
+    unnamed_lines: usize,
+    // every line's id and where its xml is, for exporting translations
+    line_ranges: Vec<(String, Range<usize>)>,
+    // the story's rooms and items, for checking what's done with them
+    world: World,
 }
 
 impl<'a> ChapterParser<'a> {
     pub fn from(source: &'a str) -> Self {
-        let doc = Rc::new(roxmltree::Document::parse(source).expect("invalid xml"));
+        Self::with_types(
+            "chapter",
+            "chapter.xml",
+            source,
+            BTreeMap::new(),
+            &World::default(),
+        )
+    }
+
+    // parses a chapter which can use variables and the world from the rest of its story
+    pub fn with_types(
+        id: &str,
+        file: &str,
+        source: &'a str,
+        types: BTreeMap<String, Type>,
+        world: &World,
+    ) -> Self {
+        let mut parser = Self::new(file, source);
+        parser.id = id.to_string();
+        parser.types = types;
+        parser.world = world.clone();
+        parser.parse();
+        parser
+    }
+
+    // the types of the variables a chapter declares or sets.
+    // errors are ignored here, parsing the chapter properly will report them
+    pub fn declarations(source: &'a str) -> BTreeMap<String, Type> {
+        let mut parser = Self::new("", source);
+        if let Some(doc) = parser.doc.clone() {
+            parser.declare_vars(doc.root_element());
+        }
+        parser.types
+    }
+
+    pub fn into_result(self) -> Result<Chapter, Vec<ParseError>> {
+        match self.chapter {
+            Some(chapter) if self.errors.is_empty() => Ok(chapter),
+            _ => Err(self.errors),
+        }
+    }
 
-        let mut parser = Self {
+    fn new(file: &str, source: &'a str) -> Self {
+        let mut errors = vec![];
+        let doc = match roxmltree::Document::parse(source) {
+            Ok(doc) => Some(Rc::new(doc)),
+            Err(e) => {
+                let pos = e.pos();
+                let kind = ErrorKind::Xml(e.to_string());
+                errors.push(ParseError::new(file, source, pos.row, pos.col, kind));
+                None
+            }
+        };
+
+        Self {
             doc,
+            file: file.to_string(),
             chapter: None,
+            errors,
             expr_stack: Some(vec![]),
             prop_stack: vec![],
-        };
-
-        parser.parse();
-        parser
+            voice: String::new(),
+            labels: BTreeMap::new(),
+            gotos: vec![],
+            handler_labels: vec![],
+            calls: vec![],
+            vars: BTreeMap::new(),
+            types: BTreeMap::new(),
+            locs: BTreeMap::new(),
+            children: BTreeMap::new(),
+            line_locs: vec![],
+            id: String::new(),
+            line_ids: BTreeMap::new(),
+            unnamed_lines: 0,
+            line_ranges: vec![],
+            world: World::default(),
+        }
     }
 
     pub fn parse(&mut self) {
-        let doc = self.doc.clone();
+        let doc = match self.doc.clone() {
+            Some(doc) => doc,
+            None => return,
+        };
+
         let e = doc.root_element();
         if e.tag_name().name() == "chapter" {
-            self.parse_chapter(self.doc.clone().root_element());
+            self.parse_chapter(e);
         } else {
-            panic!("xyz");
+            self.error(
+                e,
+                ErrorKind::UnexpectedElement {
+                    expected: "a <chapter>".to_string(),
+                    found: e.tag_name().name().to_string(),
+                },
+            );
+        }
+    }
+
+    fn error(&mut self, node: Node, kind: ErrorKind) {
+        self.error_at(node.range().start, kind);
+    }
+
+    fn error_at(&mut self, pos: usize, kind: ErrorKind) {
+        let doc = self.doc.as_ref().unwrap();
+        self.errors.push(ParseError::at(&self.file, doc, pos, kind));
+    }
+
+    // where an attribute's value starts, so errors can point right at it
+    fn attr_pos(node: Node, attr: &str) -> usize {
+        node.attribute_node(attr)
+            .map_or(node.range().start, |a| a.value_range().start)
+    }
+
+    fn require<'n>(&mut self, node: Node<'n, '_>, attr: &str) -> Option<&'n str> {
+        let value = node.attribute(attr);
+        if value.is_none() {
+            self.error(
+                node,
+                ErrorKind::MissingAttribute {
+                    element: node.tag_name().name().to_string(),
+                    attr: attr.to_string(),
+                },
+            );
         }
+        value
     }
 
     pub fn parse_chapter(&mut self, node: roxmltree::Node) {
-        let voice = node.attribute("voice").unwrap();
+        let voice = self.require(node, "voice").unwrap_or_default();
+        self.voice = voice.to_string();
+
+        self.declare_vars(node);
 
         for child in node.children() {
             self.parse_chexpr(child);
         }
 
+        for (idx, label, pos) in std::mem::take(&mut self.gotos) {
+            match self.labels.get(&label) {
+                Some(target) => *self.expr_mut(idx) = ChExpr::Jump(*target),
+                None => self.error_at(pos, ErrorKind::UndefinedLabel(label)),
+            }
+        }
+        for (idx, handler, label, pos) in std::mem::take(&mut self.handler_labels) {
+            match self.labels.get(&label).copied() {
+                Some(target) => {
+                    if let ChExpr::Command { handlers } = self.expr_mut(idx) {
+                        handlers[handler].target = target;
+                    }
+                }
+                None => self.error_at(pos, ErrorKind::UndefinedLabel(label)),
+            }
+        }
+        for (label, pos) in std::mem::take(&mut self.calls) {
+            if !self.labels.contains_key(&label) {
+                self.error_at(pos, ErrorKind::UndefinedLabel(label));
+            }
+        }
+
+        let content = self.expr_stack.take().unwrap();
+        let source = SourceMap {
+            file: self.file.clone(),
+            exprs: (0..content.len())
+                .map(|idx| self.locs.get(&idx).copied().unwrap_or_default())
+                .collect(),
+            children: (0..content.len())
+                .map(|idx| self.children.remove(&idx).unwrap_or_default())
+                .collect(),
+        };
+
         self.chapter = Some(Chapter {
+            // the story manifest gives chapters their ids
+            id: self.id.clone(),
             voice: voice.to_string(),
-            content: self.expr_stack.take().unwrap(),
+            content,
+            labels: std::mem::take(&mut self.labels),
+            vars: std::mem::take(&mut self.vars),
+            source: Some(source),
         });
     }
 
+    // finds every variable's type before we start checking expressions,
+    // so a variable can be used before the <set> that gives it a type
+    fn declare_vars(&mut self, chapter: Node) {
+        for node in chapter.descendants().filter(|n| n.is_element()) {
+            match node.tag_name().name() {
+                "var" => {
+                    let (name, src) =
+                        match (self.require(node, "name"), self.require(node, "value")) {
+                            (Some(name), Some(src)) => (name, src),
+                            _ => continue,
+                        };
+                    let value = match self.parse_expr(node, "value", src) {
+                        Some((Expr::Lit(value), _)) => value,
+                        Some(_) => {
+                            let pos = Self::attr_pos(node, "value");
+                            self.error_at(pos, ErrorKind::NotALiteral(src.to_string()));
+                            continue;
+                        }
+                        None => continue,
+                    };
+
+                    if self.vars.contains_key(name) {
+                        self.error(node, ErrorKind::DuplicateVar(name.to_string()));
+                        continue;
+                    }
+                    if let Some(ty) = self.types.get(name).filter(|ty| **ty != value.ty()) {
+                        let kind = ErrorKind::TypeMismatch {
+                            var: name.to_string(),
+                            expected: *ty,
+                            found: value.ty(),
+                        };
+                        self.error(node, kind);
+                        continue;
+                    }
+                    self.types.insert(name.to_string(), value.ty());
+                    self.vars.insert(name.to_string(), value);
+                }
+                "set" => {
+                    // a missing var is reported when the <set> itself is parsed
+                    let name = match node.attribute("var").or_else(|| node.attribute("flag")) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    let literal = match node.attribute("value") {
+                        None => Some(Type::Bool),
+                        Some(src) => match expr::parse(src, &BTreeMap::new(), &World::default()) {
+                            Ok((Expr::Lit(_), ty)) => Some(ty),
+                            _ => None,
+                        },
+                    };
+
+                    if let Some(ty) = literal {
+                        self.types.entry(name.to_string()).or_insert(ty);
+                    }
+                }
+                "input" => {
+                    if let Some(name) = node.attribute("var") {
+                        self.types.entry(name.to_string()).or_insert(Type::Str);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // parses the expression in one of node's attributes
+    fn parse_expr(&mut self, node: Node, attr: &str, src: &str) -> Option<(Expr, Type)> {
+        match expr::parse(src, &self.types, &self.world) {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                let pos = Self::attr_pos(node, attr) + e.offset;
+                let kind = ErrorKind::InvalidExpr {
+                    src: src.to_string(),
+                    message: e.message,
+                };
+                self.error_at(pos, kind);
+                None
+            }
+        }
+    }
+
+    // pushes an expression onto the current stack, returning its index
+    fn push_expr(&mut self, expr: ChExpr) -> usize {
+        let stack = self.expr_stack.as_mut().unwrap();
+        stack.push(expr);
+        stack.len() - 1
+    }
+
+    // the index of the next expression to be pushed
+    fn next_idx(&self) -> usize {
+        self.expr_stack.as_ref().unwrap().len()
+    }
+
+    fn expr_mut(&mut self, idx: usize) -> &mut ChExpr {
+        &mut self.expr_stack.as_mut().unwrap()[idx]
+    }
+
+    fn loc(&self, node: Node) -> Loc {
+        let pos = self.doc.as_ref().unwrap().text_pos_at(node.range().start);
+        Loc {
+            line: pos.row,
+            col: pos.col,
+        }
+    }
+
     fn parse_chexpr(&mut self, node: Node) {
+        let start = self.next_idx();
+        self.parse_chexpr_inner(node);
+
+        // whatever this node produced, that its children didn't claim, came from it
+        let loc = self.loc(node);
+        for idx in start..self.next_idx() {
+            self.locs.entry(idx).or_insert(loc);
+        }
+    }
+
+    fn parse_chexpr_inner(&mut self, node: Node) {
         let t = node.node_type();
         match t {
             NodeType::Element => self.parse_element(node),
             NodeType::PI => {
-                let pi = self.parse_instruction(node);
-                if let Some(stack) = &mut self.expr_stack {
-                    stack.push(ChExpr::Instruction(pi));
+                if let Some(pi) = self.parse_instruction(node) {
+                    self.push_expr(ChExpr::Instruction(pi));
                 }
             }
             NodeType::Text => {
                 if !Self::text_is_whitespace(&node) {
-                    panic!("text cannot be the child of a chapter");
+                    self.error(node, ErrorKind::UnexpectedText);
                 }
             }
             NodeType::Comment => {}
-            _ => panic!("illegal element: {:?}", node),
+            _ => unreachable!("the document root can't be inside a chapter"),
         }
     }
 
@@ -126,71 +626,307 @@ impl<'a> ChapterParser<'a> {
             "s0" | "s1" | "s2" | "s3" | "s4" | "s5" => {}
             // structure
             "line" => {
-                self.parse_line(node);
+                let idx = self.next_idx();
+                let locs = self.parse_line(node);
+                self.children.insert(idx, locs);
             }
             "await" => {
                 self.parse_await(node);
             }
-            _s => unimplemented!("<{}> not implemented", _s),
+            "wait" => {
+                if let Some(ticks) = self.parse_duration(node) {
+                    self.push_expr(ChExpr::Wait(ticks));
+                }
+            }
+            "choice" => {
+                self.parse_choice(node);
+            }
+            "random" => {
+                self.parse_random(node);
+            }
+            // control flow
+            "label" => {
+                self.parse_label(node);
+            }
+            "goto" => {
+                self.parse_goto(node);
+            }
+            "call" => {
+                self.parse_call(node);
+            }
+            "return" => {
+                self.push_expr(ChExpr::Return);
+            }
+            // variables
+            "var" => {} // declared up front
+            "set" => {
+                self.parse_set(node);
+            }
+            "if" => {
+                self.parse_if(node);
+            }
+            "input" => {
+                self.parse_input(node);
+            }
+            "commands" => {
+                self.parse_commands(node);
+            }
+            // the world
+            "go" => {
+                self.parse_go(node);
+            }
+            "move" => {
+                self.parse_move(node);
+            }
+            "state" => {
+                self.parse_state(node);
+            }
+            "else" => {
+                // else blocks are consumed by the <if> before them
+                match node.prev_sibling_element() {
+                    Some(prev) if prev.tag_name().name() == "if" => {}
+                    _ => self.error(node, ErrorKind::ElseWithoutIf),
+                }
+            }
+            s => self.error(node, ErrorKind::UnknownElement(s.to_string())),
         }
     }
 
-    fn parse_instruction(&mut self, node: Node) -> Instruction {
+    // <?play song=lowtide?>, <?play sfx=blip?> and <?stop?>
+    fn parse_instruction(&mut self, node: Node) -> Option<Instruction> {
         let pi = node.pi().unwrap();
-        match pi.target {
-            "play" => Play {
-                sound: pi.value.unwrap().to_string(),
-            },
+        let value = pi.value.unwrap_or("").trim();
+        match (pi.target, value.split_once('=')) {
+            ("play", Some(("song", sound))) => Some(Play {
+                sound: sound.to_string(),
+            }),
+            ("play", Some(("sfx", sound))) => Some(Instruction::Effect {
+                sound: sound.to_string(),
+            }),
+            ("stop", None) if value.is_empty() => Some(Instruction::Stop),
             _ => {
-                panic!("unsupported processing instruction: {:?}", pi);
+                let pi = format!("{} {}", pi.target, value);
+                self.error(node, ErrorKind::UnknownInstruction(pi.trim().to_string()));
+                None
+            }
+        }
+    }
+
+    // a <line> or <text> can have an id="..", which is what other languages' strings.xml
+    // translate it by. otherwise it's numbered by where it is among the chapter's other
+    // unnamed lines, which moves when lines are added, so those can't be translated (see ids.rs)
+    fn line_id(&mut self, node: Node) -> String {
+        let explicit = node.attribute("id");
+        let id = match explicit {
+            Some(id) => id.to_string(),
+            None => {
+                self.unnamed_lines += 1;
+                format!("{}{}{}", self.id, GENERATED, self.unnamed_lines)
+            }
+        };
+
+        let seen = self.line_ids.entry(id.clone()).or_insert(0);
+        *seen += 1;
+        match (*seen, explicit) {
+            (1, _) => id,
+            (_, Some(_)) => {
+                let pos = Self::attr_pos(node, "id");
+                self.error_at(pos, ErrorKind::DuplicateLineId(id.clone()));
+                id
             }
+            // someone named another line after this one's number
+            (n, None) => format!("{}-{}", id, n),
         }
     }
 
-    fn parse_line(&mut self, node: Node) {
+    // returns where each of the line's children came from
+    fn parse_line(&mut self, node: Node) -> Vec<Loc> {
+        let id = self.line_id(node);
+        self.line_ranges.push((id.clone(), node.range()));
         if let Some(stack) = &mut self.expr_stack {
-            stack.push(Line { content: vec![] });
+            stack.push(Line {
+                id,
+                content: vec![],
+            });
+        }
+
+        let voice = node.attribute("voice");
+        if let Some(voice) = voice {
+            self.prop_stack.push(Props::Voice(voice.to_string()));
         }
 
         for child in node.children() {
             self.parse_line_child(child);
         }
+
+        if voice.is_some() {
+            self.prop_stack.pop();
+        }
+
+        std::mem::take(&mut self.line_locs)
+    }
+
+    // adds a child to the line on top of the stack
+    fn push_child(&mut self, node: Node, child: LineChild) {
+        let loc = self.loc(node);
+        if let Some(Line { content, .. }) = self.expr_stack.as_mut().unwrap().last_mut() {
+            content.push(child);
+            self.line_locs.push(loc);
+        }
     }
 
     fn parse_line_child(&mut self, node: Node) {
         match node.node_type() {
+            NodeType::Element if node.tag_name().name() == "pause" => {
+                if let Some(ticks) = self.parse_duration(node) {
+                    self.push_child(node, LineChild::Pause(ticks));
+                }
+            }
             NodeType::Element => self.parse_property(node),
             NodeType::PI => {
-                let pi = self.parse_instruction(node);
-                if let Some(stack) = &mut self.expr_stack {
-                    if let Line { content } = stack.last_mut().unwrap() {
-                        content.push(LineChild::Instruction(pi));
-                    }
+                if let Some(pi) = self.parse_instruction(node) {
+                    self.push_child(node, LineChild::Instruction(pi));
                 }
             }
-            NodeType::Text => {
-                if !Self::text_is_whitespace(&node) {
-                    let mut span = Span {
-                        text: node.text().unwrap().to_string(),
-                        properties: TextProperties { speed: 3 }, // default speed of 3
-                    };
+            NodeType::Text if !Self::text_is_whitespace(&node) => {
+                let mut properties = TextProperties {
+                    speed: 3, // default speed of 3
+                    voice: self.voice.clone(),
+                    effects: vec![],
+                };
 
-                    for prop in &self.prop_stack {
-                        match prop {
-                            Props::Speed(n) => {
-                                span.properties.speed = n.clone();
+                for prop in &self.prop_stack {
+                    match prop {
+                        Props::Speed(n) => {
+                            properties.speed = *n;
+                        }
+                        Props::Voice(v) => {
+                            properties.voice = v.clone();
+                        }
+                        Props::Effect(e) => {
+                            if !properties.effects.contains(e) {
+                                properties.effects.push(*e);
                             }
                         }
                     }
+                }
 
-                    if let Some(stack) = &mut self.expr_stack {
-                        if let ChExpr::Line { content } = stack.last_mut().unwrap() {
-                            content.push(LineChild::Span(span));
-                        }
-                    }
+                self.parse_text(node, properties);
+            }
+            _ => {} // comments
+        }
+    }
+
+    // splits text into spans and the {expressions} between them
+    fn parse_text(&mut self, node: Node, properties: TextProperties) {
+        let text = node.text().unwrap();
+        let mut rest = text;
+        let mut span = String::new();
+        // where an offset into the text is in the source, which has its entities escaped
+        let raw = &self.doc.as_ref().unwrap().input_text()[node.range()];
+        let pos = |offset| node.range().start + source_offset(raw, offset);
+
+        while let Some(i) = rest.find(['{', '}']) {
+            span.push_str(&rest[..i]);
+            let offset = text.len() - rest.len() + i;
+            let brace = &rest[i..i + 1];
+            rest = &rest[i + 1..];
+
+            // {{ and }} are just braces
+            if rest.starts_with(brace) {
+                span.push_str(brace);
+                rest = &rest[1..];
+                continue;
+            }
+
+            let end = match rest.find('}') {
+                Some(end) if brace == "{" => end,
+                _ => {
+                    let kind = ErrorKind::UnmatchedBrace(brace.to_string());
+                    self.error_at(pos(offset), kind);
+                    return;
+                }
+            };
+            let src = &rest[..end];
+            rest = &rest[end + 1..];
+
+            if !span.is_empty() {
+                let text = std::mem::take(&mut span);
+                let properties = properties.clone();
+                self.push_child(node, LineChild::Span(Span { text, properties }));
+            }
+            match expr::parse(src, &self.types, &self.world) {
+                Ok((expr, _)) => {
+                    let properties = properties.clone();
+                    self.push_child(node, LineChild::Interpolation { expr, properties });
                 }
+                Err(e) => {
+                    let kind = ErrorKind::InvalidExpr {
+                        src: src.to_string(),
+                        message: e.message,
+                    };
+                    self.error_at(pos(offset + 1 + e.offset), kind);
+                }
+            }
+        }
+
+        span.push_str(rest);
+        if !span.is_empty() {
+            let text = span;
+            self.push_child(node, LineChild::Span(Span { text, properties }));
+        }
+    }
+
+    // ticks="30" or ms="500", for <pause> and <wait>
+    fn parse_duration(&mut self, node: Node) -> Option<u64> {
+        let mut parse = |attr: &str| {
+            let s = node.attribute(attr)?;
+            match s.parse::<u64>() {
+                Ok(n) => Some(Ok(n)),
+                Err(_) => {
+                    let kind = ErrorKind::InvalidNumber {
+                        element: node.tag_name().name().to_string(),
+                        attr: attr.to_string(),
+                        value: s.to_string(),
+                    };
+                    self.error_at(Self::attr_pos(node, attr), kind);
+                    Some(Err(()))
+                }
+            }
+        };
+
+        match (parse("ticks"), parse("ms")) {
+            (Some(Ok(ticks)), None) => Some(ticks),
+            // round up, so short pauses still happen
+            (None, Some(Ok(ms))) => match ms.checked_mul(TICKS_PER_SECOND) {
+                Some(ms) => Some(ms.div_ceil(1000)),
+                None => {
+                    let kind = ErrorKind::InvalidNumber {
+                        element: node.tag_name().name().to_string(),
+                        attr: "ms".to_string(),
+                        value: ms.to_string(),
+                    };
+                    self.error_at(Self::attr_pos(node, "ms"), kind);
+                    None
+                }
+            },
+            (Some(Err(_)), _) | (_, Some(Err(_))) => None,
+            (Some(_), Some(_)) => {
+                let kind = ErrorKind::ConflictingAttributes {
+                    element: node.tag_name().name().to_string(),
+                    attrs: "ticks and ms".to_string(),
+                };
+                self.error_at(Self::attr_pos(node, "ms"), kind);
+                None
+            }
+            (None, None) => {
+                let kind = ErrorKind::MissingAttribute {
+                    element: node.tag_name().name().to_string(),
+                    attr: "ticks or ms".to_string(),
+                };
+                self.error(node, kind);
+                None
             }
-            _ => println!("possibly malformed line, found: {:?}", node), // warn about other shit
         }
     }
 
@@ -211,8 +947,28 @@ impl<'a> ChapterParser<'a> {
             "s4" => {
                 self.prop_stack.push(ast::Props::Speed(4));
             }
-            _ => {
-                panic!("unsupported property {:?}", node);
+            "voice" => {
+                let name = self.require(node, "name").unwrap_or(&self.voice);
+                self.prop_stack.push(ast::Props::Voice(name.to_string()));
+            }
+            "wiggle" => {
+                self.prop_stack.push(ast::Props::Effect(Effect::Wiggle));
+            }
+            "wave" => {
+                self.prop_stack.push(ast::Props::Effect(Effect::Wave));
+            }
+            "shake" => {
+                self.prop_stack.push(ast::Props::Effect(Effect::Shake));
+            }
+            "rainbow" => {
+                self.prop_stack.push(ast::Props::Effect(Effect::Rainbow));
+            }
+            "fade" => {
+                self.prop_stack.push(ast::Props::Effect(Effect::FadeIn));
+            }
+            s => {
+                self.error(node, ErrorKind::UnknownProperty(s.to_string()));
+                return;
             }
         }
 
@@ -223,42 +979,1265 @@ impl<'a> ChapterParser<'a> {
         self.prop_stack.pop();
     }
 
-    fn parse_await(&mut self, node: Node) {
-        if let Some(stack) = &mut self.expr_stack {
-            stack.push(Action(Await));
+    fn parse_choice(&mut self, node: Node) {
+        let mut option_nodes = vec![];
+
+        for child in node.children() {
+            match child.node_type() {
+                NodeType::Element if child.tag_name().name() == "option" => {
+                    option_nodes.push(child);
+                }
+                NodeType::Element => {
+                    let kind = ErrorKind::UnexpectedElement {
+                        expected: "an <option>".to_string(),
+                        found: child.tag_name().name().to_string(),
+                    };
+                    self.error(child, kind);
+                }
+                NodeType::Text if !Self::text_is_whitespace(&child) => {
+                    self.error(child, ErrorKind::UnexpectedText);
+                }
+                _ => {}
+            }
         }
-    }
 
-    fn text_is_whitespace(node: &Node) -> bool {
-        if let Some(t) = node.text() {
-            return t.trim().is_empty();
+        if option_nodes.is_empty() {
+            self.error(node, ErrorKind::EmptyChoice);
+            return;
         }
-        false
+
+        let prompt = self.push_expr(ChExpr::Prompt { options: vec![] });
+        let mut options = vec![];
+        let mut exits = vec![];
+        let mut locs = vec![];
+
+        for option in option_nodes {
+            let (option, text_locs) = self.parse_option(option);
+            options.push(option);
+            locs.extend(text_locs);
+            // jump over the remaining options' content
+            exits.push(self.push_expr(ChExpr::Jump(0)));
+        }
+
+        let end = self.next_idx();
+        for exit in exits {
+            *self.expr_mut(exit) = ChExpr::Jump(end);
+        }
+
+        *self.expr_mut(prompt) = ChExpr::Prompt { options };
+        self.children.insert(prompt, locs);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::ChapterParser;
+    // <random> picks one of its variants, so the same spot doesn't always say the same thing:
+    //
+    // <random mode="shuffle">
+    //     <variant weight="2"><line>The void hums.</line></variant>
+    //     <variant><line>The void is quiet.</line></variant>
+    // </random>
+    //
+    // mode is random (the default), shuffle or cycle, and weights default to 1
+    fn parse_random(&mut self, node: Node) {
+        let mode = match node.attribute("mode") {
+            None | Some("random") => RandomMode::Weighted,
+            Some("shuffle") => RandomMode::Shuffle,
+            Some("cycle") => RandomMode::Cycle,
+            Some(mode) => {
+                let pos = Self::attr_pos(node, "mode");
+                self.error_at(pos, ErrorKind::UnknownRandomMode(mode.to_string()));
+                RandomMode::Weighted
+            }
+        };
 
-    // TODO: create tests for each parse function
+        let mut variant_nodes = vec![];
+        for child in node.children() {
+            match child.node_type() {
+                NodeType::Element if child.tag_name().name() == "variant" => {
+                    variant_nodes.push(child);
+                }
+                NodeType::Element => {
+                    let kind = ErrorKind::UnexpectedElement {
+                        expected: "a <variant>".to_string(),
+                        found: child.tag_name().name().to_string(),
+                    };
+                    self.error(child, kind);
+                }
+                NodeType::Text if !Self::text_is_whitespace(&child) => {
+                    self.error(child, ErrorKind::UnexpectedText);
+                }
+                _ => {}
+            }
+        }
 
-    #[test]
-    fn parse() {
-        let p = ChapterParser::from(
-            r#"
-        <chapter voice="universe">
-            <line><s0>...</s0></line><await/>
-            <line>The <s4>universe</s4> is silent</line><await/>
-            <line><s0>...</s0></line><await/>
-            <line>What's <s4>that?</s4></line><await/>
-            <line>A faint murmur <s4>masquerades</s4> amongst the <s4>silence.</s4></line><await/>
-            <?play song/lowtide?>
-            <line>It's <s1>you.</s1></line><await/>
-            <line><s0>...</s0></line>
-        </chapter>"#,
-        );
+        if variant_nodes.is_empty() {
+            self.error(node, ErrorKind::EmptyRandom);
+            return;
+        }
 
-        println!("{:?}", p.chapter);
+        let random = self.push_expr(ChExpr::Random {
+            mode: mode.clone(),
+            variants: vec![],
+        });
+        let mut variants = vec![];
+        let mut exits = vec![];
+
+        for variant in variant_nodes {
+            let weight = match variant.attribute("weight") {
+                None => 1,
+                Some(weight) => match weight.parse::<u32>() {
+                    Ok(weight) if weight > 0 => weight,
+                    _ => {
+                        let kind = ErrorKind::InvalidNumber {
+                            element: "variant".to_string(),
+                            attr: "weight".to_string(),
+                            value: weight.to_string(),
+                        };
+                        self.error_at(Self::attr_pos(variant, "weight"), kind);
+                        1
+                    }
+                },
+            };
+            variants.push(Variant {
+                weight,
+                target: self.next_idx(),
+            });
+            for child in variant.children() {
+                self.parse_chexpr(child);
+            }
+            // jump over the remaining variants' content
+            exits.push(self.push_expr(ChExpr::Jump(0)));
+        }
+
+        let end = self.next_idx();
+        for exit in exits {
+            *self.expr_mut(exit) = ChExpr::Jump(end);
+        }
+
+        *self.expr_mut(random) = ChExpr::Random { mode, variants };
+    }
+
+    // also returns where the option's text came from
+    fn parse_option(&mut self, node: Node) -> (PromptOption, Vec<Loc>) {
+        let target = self.next_idx();
+        let mut text = None;
+        let mut locs = vec![];
+
+        for child in node.children() {
+            if child.is_element() && child.tag_name().name() == "text" {
+                // <text> is parsed like a line, then popped back off
+                locs = self.parse_line(child);
+                if let Some(Line { id, content }) = self.expr_stack.as_mut().unwrap().pop() {
+                    text = Some((id, content));
+                }
+            } else {
+                self.parse_chexpr(child);
+            }
+        }
+
+        if text.is_none() {
+            self.error(node, ErrorKind::MissingText);
+        }
+
+        let (id, text) = text.unwrap_or_default();
+        let option = PromptOption { id, text, target };
+        (option, locs)
+    }
+
+    fn parse_label(&mut self, node: Node) {
+        let name = match self.require(node, "name") {
+            Some(name) => name,
+            None => return,
+        };
+        let idx = self.next_idx();
+
+        if self.labels.insert(name.to_string(), idx).is_some() {
+            self.error(node, ErrorKind::DuplicateLabel(name.to_string()));
+        }
+    }
+
+    fn parse_goto(&mut self, node: Node) {
+        if let Some(chapter) = node.attribute("chapter") {
+            // these are checked once the whole story has been parsed
+            self.push_expr(ChExpr::GotoChapter {
+                chapter: chapter.to_string(),
+                label: node.attribute("label").map(str::to_string),
+            });
+            return;
+        }
+
+        let label = match self.require(node, "label") {
+            Some(label) => label,
+            None => return,
+        };
+        let idx = self.push_expr(ChExpr::Jump(0));
+        let pos = Self::attr_pos(node, "label");
+        self.gotos.push((idx, label.to_string(), pos));
+    }
+
+    fn parse_call(&mut self, node: Node) {
+        let label = match self.require(node, "label") {
+            Some(label) => label.to_string(),
+            None => return,
+        };
+        let chapter = node.attribute("chapter").map(str::to_string);
+        // calls into other chapters are checked once the whole story has been parsed
+        if chapter.is_none() {
+            let pos = Self::attr_pos(node, "label");
+            self.calls.push((label.clone(), pos));
+        }
+        self.push_expr(ChExpr::Call { chapter, label });
+    }
+
+    fn parse_set(&mut self, node: Node) {
+        let var = match node.attribute("var").or_else(|| node.attribute("flag")) {
+            Some(var) => var,
+            None => {
+                let kind = ErrorKind::MissingAttribute {
+                    element: "set".to_string(),
+                    attr: "var or flag".to_string(),
+                };
+                self.error(node, kind);
+                return;
+            }
+        };
+
+        // a bare <set flag="x"/> raises the flag
+        let (value, ty) = match node.attribute("value") {
+            Some(src) => match self.parse_expr(node, "value", src) {
+                Some(parsed) => parsed,
+                None => return,
+            },
+            None => (Expr::Lit(Value::Bool(true)), Type::Bool),
+        };
+
+        match self.types.get(var) {
+            Some(expected) if *expected != ty => {
+                let kind = ErrorKind::TypeMismatch {
+                    var: var.to_string(),
+                    expected: *expected,
+                    found: ty,
+                };
+                self.error_at(Self::attr_pos(node, "value"), kind);
+                return;
+            }
+            Some(_) => {}
+            None => {
+                self.types.insert(var.to_string(), ty);
+            }
+        }
+
+        self.push_expr(ChExpr::Set {
+            var: var.to_string(),
+            value,
+        });
+    }
+
+    fn parse_input(&mut self, node: Node) {
+        let (var, max) = match (self.require(node, "var"), self.require(node, "max")) {
+            (Some(var), Some(max)) => (var, max),
+            _ => return,
+        };
+
+        let max = match max.parse::<usize>() {
+            Ok(max) if max > 0 => max,
+            _ => {
+                let kind = ErrorKind::InvalidNumber {
+                    element: "input".to_string(),
+                    attr: "max".to_string(),
+                    value: max.to_string(),
+                };
+                self.error_at(Self::attr_pos(node, "max"), kind);
+                return;
+            }
+        };
+
+        // declare_vars made it a string, unless something else got to it first
+        if let Some(ty) = self.types.get(var).filter(|ty| **ty != Type::Str) {
+            let kind = ErrorKind::TypeMismatch {
+                var: var.to_string(),
+                expected: *ty,
+                found: Type::Str,
+            };
+            self.error_at(Self::attr_pos(node, "var"), kind);
+            return;
+        }
+
+        self.push_expr(ChExpr::Input {
+            var: var.to_string(),
+            max,
+        });
+    }
+
+    // <commands>
+    //     <on verb="look" label="look-around"/>
+    //     <on verb="take" noun="key" label="take-key"/>
+    // </commands>
+    // the words are checked against the story's vocab once it's all parsed
+    fn parse_commands(&mut self, node: Node) {
+        let idx = self.next_idx();
+        let mut handlers = vec![];
+
+        for child in node.children() {
+            match child.node_type() {
+                NodeType::Element if child.tag_name().name() == "on" => {}
+                NodeType::Element => {
+                    let kind = ErrorKind::UnexpectedElement {
+                        expected: "an <on>".to_string(),
+                        found: child.tag_name().name().to_string(),
+                    };
+                    self.error(child, kind);
+                    continue;
+                }
+                NodeType::Text if !Self::text_is_whitespace(&child) => {
+                    self.error(child, ErrorKind::UnexpectedText);
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let (verb, label) = match (self.require(child, "verb"), self.require(child, "label")) {
+                (Some(verb), Some(label)) => (verb, label),
+                _ => continue,
+            };
+            let pos = Self::attr_pos(child, "label");
+            self.handler_labels
+                .push((idx, handlers.len(), label.to_string(), pos));
+            handlers.push(CommandHandler {
+                verb: story::normalize(verb),
+                noun: child.attribute("noun").map(story::normalize),
+                target: 0,
+            });
+        }
+
+        self.push_expr(ChExpr::Command { handlers });
+    }
+
+    // <go room="hallway"/> or <go exit="north"/>
+    fn parse_go(&mut self, node: Node) {
+        let destination = match (node.attribute("room"), node.attribute("exit")) {
+            (Some(room), None) if self.world.rooms.contains_key(room) => {
+                Destination::Room(room.to_string())
+            }
+            (Some(room), None) => {
+                let kind = ErrorKind::UndefinedRoom(room.to_string());
+                self.error_at(Self::attr_pos(node, "room"), kind);
+                return;
+            }
+            (None, Some(dir)) if self.world.rooms.values().any(|r| r.exits.contains_key(dir)) => {
+                Destination::Exit(dir.to_string())
+            }
+            (None, Some(dir)) => {
+                let kind = ErrorKind::UndefinedExit(dir.to_string());
+                self.error_at(Self::attr_pos(node, "exit"), kind);
+                return;
+            }
+            _ => {
+                let kind = ErrorKind::MissingAttribute {
+                    element: "go".to_string(),
+                    attr: "room or exit".to_string(),
+                };
+                self.error(node, kind);
+                return;
+            }
+        };
+
+        self.push_expr(ChExpr::Go(destination));
+    }
+
+    // <move item="brass key" to="inventory"/>, or to="here", or to a room
+    fn parse_move(&mut self, node: Node) {
+        let (item, to) = match (self.require(node, "item"), self.require(node, "to")) {
+            (Some(item), Some(to)) => (item, to),
+            _ => return,
+        };
+        let to = match to {
+            ast::INVENTORY => Place::Inventory,
+            world::HERE => Place::Here,
+            room if self.world.rooms.contains_key(room) => Place::Room(room.to_string()),
+            room => {
+                let kind = ErrorKind::UndefinedRoom(room.to_string());
+                self.error_at(Self::attr_pos(node, "to"), kind);
+                return;
+            }
+        };
+
+        if let Some(item) = self.item(node) {
+            self.push_expr(ChExpr::Move { item, to });
+        }
+    }
+
+    // <state item="door" value="open"/>
+    fn parse_state(&mut self, node: Node) {
+        let state = match self.require(node, "value") {
+            Some(state) => state.to_string(),
+            None => return,
+        };
+
+        if let Some(item) = self.item(node) {
+            self.push_expr(ChExpr::SetState { item, state });
+        }
+    }
+
+    // the item="..", if the world has it
+    fn item(&mut self, node: Node) -> Option<String> {
+        let item = node.attribute("item")?;
+        if !self.world.items.contains_key(item) {
+            let kind = ErrorKind::UndefinedItem(item.to_string());
+            self.error_at(Self::attr_pos(node, "item"), kind);
+            return None;
+        }
+        Some(item.to_string())
+    }
+
+    fn parse_if(&mut self, node: Node) {
+        // keep going with a bad condition, so errors inside the <if> are still found
+        let cond = match self.require(node, "cond") {
+            Some(src) => match self.parse_expr(node, "cond", src) {
+                Some((cond, Type::Bool)) => cond,
+                Some((_, ty)) => {
+                    let pos = Self::attr_pos(node, "cond");
+                    self.error_at(pos, ErrorKind::ConditionNotBool(ty));
+                    Expr::Lit(Value::Bool(false))
+                }
+                None => Expr::Lit(Value::Bool(false)),
+            },
+            None => Expr::Lit(Value::Bool(false)),
+        };
+
+        let branch = self.push_expr(ChExpr::Branch {
+            cond: cond.clone(),
+            otherwise: 0,
+        });
+
+        for child in node.children() {
+            self.parse_chexpr(child);
+        }
+
+        let else_node = node
+            .next_sibling_element()
+            .filter(|n| n.tag_name().name() == "else");
+
+        let otherwise = if let Some(else_node) = else_node {
+            // skip the else block once the if block is done
+            let exit = self.push_expr(ChExpr::Jump(0));
+            let otherwise = self.next_idx();
+
+            for child in else_node.children() {
+                self.parse_chexpr(child);
+            }
+
+            *self.expr_mut(exit) = ChExpr::Jump(self.next_idx());
+            otherwise
+        } else {
+            self.next_idx()
+        };
+
+        *self.expr_mut(branch) = ChExpr::Branch { cond, otherwise };
+    }
+
+    fn parse_await(&mut self, node: Node) {
+        if let Some(stack) = &mut self.expr_stack {
+            stack.push(Action(Await));
+        }
+    }
+
+    fn text_is_whitespace(node: &Node) -> bool {
+        if let Some(t) = node.text() {
+            return t.trim().is_empty();
+        }
+        false
+    }
+}
+
+// the byte in a text node's source that's `offset` bytes into its unescaped text.
+// e.g. in "a &amp; b }", the } is 6 bytes into the text but 10 into the source
+fn source_offset(raw: &str, offset: usize) -> usize {
+    let mut unescaped = 0;
+    let mut chars = raw.char_indices();
+    while unescaped < offset {
+        let (i, ch) = match chars.next() {
+            Some(next) => next,
+            None => return raw.len(),
+        };
+        let entity = raw[i..]
+            .find(';')
+            .filter(|_| ch == '&')
+            .map(|end| &raw[i + 1..i + end]);
+        unescaped += match entity {
+            Some(entity) => {
+                // skip the rest of it
+                chars.nth(entity.len());
+                let code = match entity.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                };
+                code.and_then(char::from_u32).map_or(1, char::len_utf8)
+            }
+            None => ch.len_utf8(),
+        };
+    }
+    chars.next().map_or(raw.len(), |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ChapterParser, ErrorKind};
+    use ir::ast::Instruction::Play;
+    use ir::ast::{
+        BinOp, ChExpr, Chapter, Destination, Effect, Expr, Instruction, Item, LineChild, Loc,
+        Place, Query, RandomMode, Room, Span, Type, UnOp, Value, Variant, World,
+    };
+    use std::collections::BTreeMap;
+
+    // TODO: create tests for each parse function
+
+    // the kinds of errors in a chapter, in the order they were found
+    fn errors(source: &str) -> Vec<ErrorKind> {
+        let p = ChapterParser::from(source);
+        p.errors.into_iter().map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn parse() {
+        let p = ChapterParser::from(
+            r#"
+        <chapter voice="universe">
+            <line><s0>...</s0></line><await/>
+            <line>The <s4>universe</s4> is silent</line><await/>
+            <line><s0>...</s0></line><await/>
+            <line>What's <s4>that?</s4></line><await/>
+            <line>A faint murmur <s4>masquerades</s4> amongst the <s4>silence.</s4></line><await/>
+            <?play song=lowtide?>
+            <line>It's <s1>you.</s1></line><await/>
+            <line><s0>...</s0></line>
+        </chapter>"#,
+        );
+
+        println!("{:?}", p.chapter);
+    }
+
+    #[test]
+    fn parse_choice() {
+        let p = ChapterParser::from(
+            r#"
+        <chapter voice="universe">
+            <line>Who are you?</line>
+            <choice>
+                <option>
+                    <text>I don't <s4>know.</s4></text>
+                    <line>Neither do I.</line><await/>
+                </option>
+                <option><text>Nobody.</text></option>
+            </choice>
+            <line><s0>...</s0></line>
+        </chapter>"#,
+        );
+
+        let chapter = p.chapter.unwrap();
+        // line, prompt, (line, await, jump), (jump), line
+        assert_eq!(chapter.content.len(), 7);
+
+        if let ChExpr::Prompt { options } = &chapter.content[1] {
+            assert_eq!(options.len(), 2);
+            assert_eq!(options[0].text.len(), 2);
+            assert_eq!(options[0].target, 2);
+            assert!(matches!(options[1].text[0], LineChild::Span(_)));
+            assert_eq!(options[1].target, 5);
+        } else {
+            panic!("expected a prompt, found {:?}", chapter.content[1]);
+        }
+
+        assert_eq!(chapter.content[4], ChExpr::Jump(6));
+        assert_eq!(chapter.content[5], ChExpr::Jump(6));
+    }
+
+    #[test]
+    fn parse_random() {
+        let p = ChapterParser::from(
+            r#"
+        <chapter voice="universe">
+            <random mode="shuffle">
+                <variant weight="3"><line>The void hums.</line><await/></variant>
+                <variant><line>The void is quiet.</line></variant>
+            </random>
+            <random><variant/></random>
+        </chapter>"#,
+        );
+
+        let chapter = p.chapter.unwrap();
+        // random, (line, await, jump), (line, jump), random, (jump)
+        assert_eq!(
+            chapter.content[0],
+            ChExpr::Random {
+                mode: RandomMode::Shuffle,
+                variants: vec![
+                    Variant {
+                        weight: 3,
+                        target: 1
+                    },
+                    Variant {
+                        weight: 1,
+                        target: 4
+                    },
+                ]
+            }
+        );
+        assert_eq!(chapter.content[3], ChExpr::Jump(6));
+        assert_eq!(chapter.content[5], ChExpr::Jump(6));
+        assert!(matches!(
+            chapter.content[6],
+            ChExpr::Random {
+                mode: RandomMode::Weighted,
+                ..
+            }
+        ));
+        assert_eq!(chapter.content[7], ChExpr::Jump(8));
+
+        assert_eq!(
+            errors(
+                r#"<chapter voice="universe">
+                    <random mode="often"><variant weight="0"/><line>Hm.</line></random>
+                    <random/>
+                </chapter>"#
+            ),
+            vec![
+                ErrorKind::UnknownRandomMode("often".to_string()),
+                ErrorKind::UnexpectedElement {
+                    expected: "a <variant>".to_string(),
+                    found: "line".to_string()
+                },
+                ErrorKind::InvalidNumber {
+                    element: "variant".to_string(),
+                    attr: "weight".to_string(),
+                    value: "0".to_string()
+                },
+                ErrorKind::EmptyRandom,
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_goto() {
+        let p = ChapterParser::from(
+            r#"
+        <chapter voice="universe">
+            <goto label="end"/>
+            <label name="top"/>
+            <line>Again.</line><await/>
+            <goto label="top"/>
+            <label name="end"/>
+            <goto label="top"/>
+        </chapter>"#,
+        );
+
+        let chapter = p.chapter.unwrap();
+        assert_eq!(chapter.labels["top"], 1);
+        assert_eq!(chapter.labels["end"], 4);
+        assert_eq!(chapter.content[0], ChExpr::Jump(4));
+        assert_eq!(chapter.content[3], ChExpr::Jump(1));
+        assert_eq!(chapter.content[4], ChExpr::Jump(1));
+    }
+
+    #[test]
+    fn goto_undefined_label() {
+        assert_eq!(
+            errors(r#"<chapter voice="universe"><goto label="nowhere"/></chapter>"#),
+            vec![ErrorKind::UndefinedLabel("nowhere".to_string())]
+        );
+    }
+
+    #[test]
+    fn parse_call() {
+        let p = ChapterParser::from(
+            r#"
+        <chapter voice="universe">
+            <call label="hum"/>
+            <call chapter="interludes" label="hum"/>
+            <goto label="end"/>
+            <label name="hum"/>
+            <line>The void hums.</line><await/>
+            <return/>
+            <label name="end"/>
+        </chapter>"#,
+        );
+
+        let chapter = p.chapter.unwrap();
+        assert_eq!(
+            chapter.content[0],
+            ChExpr::Call {
+                chapter: None,
+                label: "hum".to_string()
+            }
+        );
+        assert_eq!(
+            chapter.content[1],
+            ChExpr::Call {
+                chapter: Some("interludes".to_string()),
+                label: "hum".to_string()
+            }
+        );
+        assert_eq!(chapter.content[5], ChExpr::Return);
+
+        assert_eq!(
+            errors(r#"<chapter voice="universe"><call label="nowhere"/><call/></chapter>"#),
+            vec![
+                ErrorKind::MissingAttribute {
+                    element: "call".to_string(),
+                    attr: "label".to_string()
+                },
+                ErrorKind::UndefinedLabel("nowhere".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_if_else() {
+        let p = ChapterParser::from(
+            r#"
+        <chapter voice="universe">
+            <var name="visits" value="0"/>
+            <set flag="met"/>
+            <set var="name" value="'Devin'"/>
+            <if cond="!met">
+                <line>Who are you?</line>
+            </if>
+            <else>
+                <set var="visits" value="visits + 1"/>
+                <line>You again.</line>
+            </else>
+            <if cond="visits >= 2 &amp;&amp; name == 'Devin'"><await/></if>
+        </chapter>"#,
+        );
+
+        let chapter = p.chapter.unwrap();
+        let c = &chapter.content;
+        let visits = || Box::new(Expr::Var("visits".to_string(), Type::Int));
+
+        assert_eq!(chapter.vars["visits"], Value::Int(0));
+        assert_eq!(
+            c[0],
+            ChExpr::Set {
+                var: "met".to_string(),
+                value: Expr::Lit(Value::Bool(true))
+            }
+        );
+        assert_eq!(
+            c[1],
+            ChExpr::Set {
+                var: "name".to_string(),
+                value: Expr::Lit(Value::Str("Devin".to_string()))
+            }
+        );
+        // branch, line, jump, set, line, branch, await
+        assert_eq!(
+            c[2],
+            ChExpr::Branch {
+                cond: Expr::Unary(
+                    UnOp::Not,
+                    Box::new(Expr::Var("met".to_string(), Type::Bool))
+                ),
+                otherwise: 5
+            }
+        );
+        assert_eq!(c[4], ChExpr::Jump(7));
+        assert_eq!(
+            c[5],
+            ChExpr::Set {
+                var: "visits".to_string(),
+                value: Expr::Binary(BinOp::Add, visits(), Box::new(Expr::Lit(Value::Int(1))))
+            }
+        );
+        assert!(matches!(c[7], ChExpr::Branch { otherwise: 9, .. }));
+        assert_eq!(c.len(), 9);
+
+        // chapters have to survive the trip through compile_ir's bincode
+        let encoded = bincode::serialize(&Some(&chapter)).unwrap();
+        let decoded: Option<Chapter> = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.as_ref(), Some(&chapter));
+    }
+
+    #[test]
+    fn condition_type_error() {
+        assert_eq!(
+            errors(
+                r#"<chapter voice="universe"><var name="trust" value="1"/><if cond="trust"/></chapter>"#
+            ),
+            vec![ErrorKind::ConditionNotBool(Type::Int)]
+        );
+    }
+
+    #[test]
+    fn set_type_error() {
+        assert_eq!(
+            errors(
+                r#"<chapter voice="universe"><set flag="met"/><set var="met" value="3"/></chapter>"#
+            ),
+            vec![ErrorKind::TypeMismatch {
+                var: "met".to_string(),
+                expected: Type::Bool,
+                found: Type::Int
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_input() {
+        let p = ChapterParser::from(
+            r#"<chapter voice="universe"><input var="name" max="12"/><line>Hi, {name}.</line></chapter>"#,
+        );
+        let chapter = p.chapter.unwrap();
+        assert_eq!(
+            chapter.content[0],
+            ChExpr::Input {
+                var: "name".to_string(),
+                max: 12
+            }
+        );
+
+        assert_eq!(
+            errors(
+                r#"<chapter voice="universe"><var name="age" value="0"/><input var="age" max="3"/><input var="name" max="0"/></chapter>"#
+            ),
+            vec![
+                ErrorKind::TypeMismatch {
+                    var: "age".to_string(),
+                    expected: Type::Int,
+                    found: Type::Str
+                },
+                ErrorKind::InvalidNumber {
+                    element: "input".to_string(),
+                    attr: "max".to_string(),
+                    value: "0".to_string()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_world_changes() {
+        let mut world = World::default();
+        let mut cell = Room::default();
+        cell.exits.insert("north".to_string(), "cell".to_string());
+        world.rooms.insert("cell".to_string(), cell);
+        let key = Item {
+            location: "cell".to_string(),
+            state: String::new(),
+        };
+        world.items.insert("key".to_string(), key);
+
+        let parse =
+            |source| ChapterParser::with_types("c", "c.xml", source, BTreeMap::new(), &world);
+        let p = parse(
+            r#"<chapter voice="universe">
+                <if cond="!has('key')"><move item="key" to="inventory"/></if>
+                <go exit="north"/>
+                <move item="key" to="here"/>
+                <state item="key" value="rusty"/>
+                <go room="cell"/>
+            </chapter>"#,
+        );
+        let c = p.into_result().unwrap().content;
+        assert_eq!(
+            c[0],
+            ChExpr::Branch {
+                cond: Expr::Unary(
+                    UnOp::Not,
+                    Box::new(Expr::Query(Query::Has("key".to_string())))
+                ),
+                otherwise: 2
+            }
+        );
+        assert_eq!(
+            c[1],
+            ChExpr::Move {
+                item: "key".to_string(),
+                to: Place::Inventory
+            }
+        );
+        assert_eq!(c[2], ChExpr::Go(Destination::Exit("north".to_string())));
+        assert_eq!(
+            c[4],
+            ChExpr::SetState {
+                item: "key".to_string(),
+                state: "rusty".to_string()
+            }
+        );
+        assert_eq!(c[5], ChExpr::Go(Destination::Room("cell".to_string())));
+
+        let p = parse(
+            r#"<chapter voice="universe">
+                <go exit="up"/>
+                <move item="lamp" to="here"/>
+                <move item="key" to="attic"/>
+                <go/>
+            </chapter>"#,
+        );
+        let kinds: Vec<_> = p.errors.into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ErrorKind::UndefinedExit("up".to_string()),
+                ErrorKind::UndefinedItem("lamp".to_string()),
+                ErrorKind::UndefinedRoom("attic".to_string()),
+                ErrorKind::MissingAttribute {
+                    element: "go".to_string(),
+                    attr: "room or exit".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn else_without_if() {
+        assert_eq!(
+            errors(r#"<chapter voice="universe"><line>hi</line><else/></chapter>"#),
+            vec![ErrorKind::ElseWithoutIf]
+        );
+    }
+
+    #[test]
+    fn parse_voices() {
+        let p = ChapterParser::from(
+            r#"
+        <chapter voice="universe">
+            <line>The void <voice name="stranger">hums</voice>.</line>
+            <line voice="stranger">Hello, <s4><voice name="universe">you</voice></s4>.</line>
+        </chapter>"#,
+        );
+
+        let chapter = p.chapter.unwrap();
+        let voices = |idx: usize| -> Vec<String> {
+            match &chapter.content[idx] {
+                ChExpr::Line { content, .. } => content
+                    .iter()
+                    .map(|child| match child {
+                        LineChild::Span(Span { properties, .. }) => properties.voice.clone(),
+                        _ => panic!("expected a span"),
+                    })
+                    .collect(),
+                expr => panic!("expected a line, found {:?}", expr),
+            }
+        };
+
+        assert_eq!(voices(0), vec!["universe", "stranger", "universe"]);
+        assert_eq!(voices(1), vec!["stranger", "universe", "stranger"]);
+    }
+
+    #[test]
+    fn parse_effects() {
+        let p = ChapterParser::from(
+            r#"
+        <chapter voice="universe">
+            <line><wave>The <rainbow>void <wave>hums</wave></rainbow></wave> <s1>softly</s1></line>
+        </chapter>"#,
+        );
+
+        let chapter = p.chapter.unwrap();
+        let effects: Vec<Vec<Effect>> = match &chapter.content[0] {
+            ChExpr::Line { content, .. } => content
+                .iter()
+                .map(|child| match child {
+                    LineChild::Span(Span { properties, .. }) => properties.effects.clone(),
+                    _ => panic!("expected a span"),
+                })
+                .collect(),
+            expr => panic!("expected a line, found {:?}", expr),
+        };
+
+        assert_eq!(
+            effects,
+            vec![
+                vec![Effect::Wave],
+                vec![Effect::Wave, Effect::Rainbow],
+                vec![Effect::Wave, Effect::Rainbow],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_pauses() {
+        let p = ChapterParser::from(
+            r#"
+        <chapter voice="universe">
+            <line>But wait<pause ms="500"/> -- who <s4>are<pause ticks="3"/></s4> you?</line>
+            <wait ms="1"/>
+        </chapter>"#,
+        );
+
+        let chapter = p.chapter.unwrap();
+        if let ChExpr::Line { content, .. } = &chapter.content[0] {
+            assert_eq!(content[1], LineChild::Pause(30));
+            assert_eq!(content[4], LineChild::Pause(3));
+        } else {
+            panic!("expected a line");
+        }
+        assert_eq!(chapter.content[1], ChExpr::Wait(1));
+
+        assert_eq!(
+            errors(
+                r#"<chapter voice="universe">
+                    <wait ms="18446744073709551615"/>
+                    <wait ticks="1" ms="1"/>
+                </chapter>"#
+            ),
+            vec![
+                ErrorKind::InvalidNumber {
+                    element: "wait".to_string(),
+                    attr: "ms".to_string(),
+                    value: "18446744073709551615".to_string()
+                },
+                ErrorKind::ConflictingAttributes {
+                    element: "wait".to_string(),
+                    attrs: "ticks and ms".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_instructions() {
+        let p = ChapterParser::from(
+            r#"
+        <chapter voice="universe">
+            <?play song=lowtide?>
+            <line>Did you hear <?play sfx=blip?>that?</line>
+            <?stop?>
+        </chapter>"#,
+        );
+
+        let chapter = p.chapter.unwrap();
+        assert_eq!(
+            chapter.content[0],
+            ChExpr::Instruction(Play {
+                sound: "lowtide".to_string()
+            })
+        );
+        if let ChExpr::Line { content, .. } = &chapter.content[1] {
+            assert_eq!(
+                content[1],
+                LineChild::Instruction(Instruction::Effect {
+                    sound: "blip".to_string()
+                })
+            );
+        } else {
+            panic!("expected a line");
+        }
+        assert_eq!(chapter.content[2], ChExpr::Instruction(Instruction::Stop));
+    }
+
+    #[test]
+    fn instruction_bad_value() {
+        assert_eq!(
+            errors(r#"<chapter voice="universe"><?play song/lowtide?></chapter>"#),
+            vec![ErrorKind::UnknownInstruction(
+                "play song/lowtide".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn located_errors() {
+        let p = ChapterParser::with_types(
+            "intro",
+            "intro.xml",
+            r#"<chapter voice="universe">
+    <line>Hello.</line>
+    <frobnicate/>
+    <if cond="1 +"><wait/></if>
+</chapter>"#,
+            BTreeMap::new(),
+            &World::default(),
+        );
+
+        // every error is collected, not just the first
+        let found: Vec<_> = p.errors.iter().map(|e| (e.line, e.col)).collect();
+        assert_eq!(found, vec![(3, 5), (4, 18), (4, 20)]);
+        assert_eq!(
+            p.errors[0].to_string(),
+            "error: <frobnicate> isn't a chapter expression\n \
+              --> intro.xml:3:5\n  \
+               |\n\
+             3 |     <frobnicate/>\n  \
+               |     ^"
+        );
+        assert!(matches!(p.errors[1].kind, ErrorKind::InvalidExpr { .. }));
+        assert!(p.into_result().is_err());
+    }
+
+    #[test]
+    fn invalid_xml() {
+        let e = errors("<chapter voice=\"universe\">\n<line>unclosed</chapter>");
+        assert!(matches!(e[..], [ErrorKind::Xml(_)]));
+    }
+
+    #[test]
+    fn compile_every_language() {
+        let src = std::env::temp_dir().join("void-compile-all");
+        let out = src.join("out");
+        // whatever an earlier run left behind
+        let _ = std::fs::remove_dir_all(&src);
+        for lang in &["en", "fr"] {
+            std::fs::create_dir_all(src.join(lang)).unwrap();
+        }
+        std::fs::write(
+            src.join("en").join("story.xml"),
+            r#"<story voices="../voices.xml"><chapter id="intro" src="intro.xml"/></story>"#,
+        )
+        .unwrap();
+        std::fs::write(
+            src.join("en").join("intro.xml"),
+            r#"<chapter voice="universe"><line id="hi">hi</line></chapter>"#,
+        )
+        .unwrap();
+        std::fs::write(
+            src.join("fr").join("strings.xml"),
+            r#"<strings><line id="hi">salut</line></strings>"#,
+        )
+        .unwrap();
+        std::fs::write(
+            src.join("voices.xml"),
+            r#"<voices><voice name="universe"/></voices>"#,
+        )
+        .unwrap();
+
+        // out has neither, so it isn't a language
+        let langs = crate::compile_all(&src, &out, &Default::default()).unwrap();
+        assert_eq!(langs, vec!["en", "fr", "pseudo"]);
+        assert!(out.join("en").join("story.bincode").is_file());
+        assert!(out.join("fr").join("strings.bincode").is_file());
+        assert!(out.join("pseudo").join("strings.bincode").is_file());
+
+        // two stories would be two different structures
+        std::fs::copy(
+            src.join("en").join("story.xml"),
+            src.join("fr").join("story.xml"),
+        )
+        .unwrap();
+        let e = crate::compile_all(&src, &out, &Default::default()).unwrap_err();
+        std::fs::remove_file(src.join("fr").join("story.xml")).unwrap();
+        assert!(matches!(e[0].kind, ErrorKind::MultipleBaseLanguages(_)));
+
+        // a language that's gone doesn't leave its strings behind
+        std::fs::remove_dir_all(src.join("fr")).unwrap();
+        std::fs::write(out.join("notes.txt"), "not ours").unwrap();
+        let langs = crate::compile_all(&src, &out, &Default::default()).unwrap();
+        assert_eq!(langs, vec!["en", "pseudo"]);
+        assert!(!out.join("fr").exists());
+        assert!(out.join("notes.txt").is_file());
+
+        // release builds don't want pseudo, so one from a debug build goes
+        let release = crate::CompileOptions {
+            source_maps: false,
+            pseudo: false,
+        };
+        let langs = crate::compile_all(&src, &out, &release).unwrap();
+        assert_eq!(langs, vec!["en"]);
+        assert!(!out.join("pseudo").exists());
+    }
+
+    #[test]
+    fn line_ids() {
+        let p = ChapterParser::from(
+            r#"<chapter voice="universe">
+    <line>...</line>
+    <line id="hello">Hello.</line>
+    <line>...</line>
+    <choice><option><text id="bye">Bye.</text></option></choice>
+</chapter>"#,
+        );
+
+        let chapter = p.chapter.unwrap();
+        let ids: Vec<_> = chapter.lines().map(|(id, _)| id.to_string()).collect();
+        // the same text, but not the same line
+        assert_eq!(ids, ["chapter~1", "hello", "chapter~2", "bye"]);
+
+        let p = ChapterParser::from(
+            r#"<chapter voice="universe"><line id="chapter~1">A</line><line>B</line></chapter>"#,
+        );
+        let ids: Vec<_> = p
+            .chapter
+            .as_ref()
+            .unwrap()
+            .lines()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, ["chapter~1", "chapter~1-2"]);
+
+        let e = errors(
+            r#"<chapter voice="universe"><line id="a">A</line><line id="a">B</line></chapter>"#,
+        );
+        assert_eq!(e, vec![ErrorKind::DuplicateLineId("a".to_string())]);
+    }
+
+    #[test]
+    fn interpolation() {
+        let p = ChapterParser::from(
+            r#"<chapter voice="universe">
+    <var name="name" value="'you'"/>
+    <var name="trust" value="0"/>
+    <line>It's <s4>{name}</s4>, {{again}} {trust + 1}</line>
+</chapter>"#,
+        );
+
+        let chapter = p.chapter.unwrap();
+        let content = match &chapter.content[0] {
+            ChExpr::Line { content, .. } => content,
+            expr => panic!("expected a line, found {:?}", expr),
+        };
+        let text: Vec<_> = content
+            .iter()
+            .map(|child| match child {
+                LineChild::Span(span) => span.text.clone(),
+                LineChild::Interpolation { expr, properties } => {
+                    format!("{:?} at speed {}", expr, properties.speed)
+                }
+                child => panic!("unexpected {:?}", child),
+            })
+            .collect();
+        assert_eq!(
+            text,
+            vec![
+                "It's ".to_string(),
+                "Var(\"name\", Str) at speed 4".to_string(),
+                ", {again} ".to_string(),
+                "Binary(Add, Var(\"trust\", Int), Lit(Int(1))) at speed 3".to_string(),
+            ]
+        );
+
+        let p = ChapterParser::from(
+            r#"<chapter voice="universe"><line>Hi {nobody}, {</line><line>}</line></chapter>"#,
+        );
+        let found: Vec<_> = p.errors.iter().map(|e| (&e.kind, e.col)).collect();
+        assert!(matches!(found[0], (ErrorKind::InvalidExpr { .. }, 37)));
+        assert_eq!(found[1], (&ErrorKind::UnmatchedBrace("{".to_string()), 46));
+        assert_eq!(found[2], (&ErrorKind::UnmatchedBrace("}".to_string()), 60));
+
+        // entities are shorter once they're unescaped, but the column is the source's
+        let p = ChapterParser::from(
+            r#"<chapter voice="universe"><line>a &amp; {1 +} &#233; }</line></chapter>"#,
+        );
+        let found: Vec<_> = p.errors.iter().map(|e| (&e.kind, e.col)).collect();
+        assert!(matches!(found[0], (ErrorKind::InvalidExpr { .. }, 45)));
+        assert_eq!(found[1], (&ErrorKind::UnmatchedBrace("}".to_string()), 54));
+    }
+
+    #[test]
+    fn source_map() {
+        let p = ChapterParser::from(
+            r#"<chapter voice="universe">
+    <line>Hi <s4>there</s4>.</line>
+    <choice>
+        <option><text>Bye.</text><await/></option>
+    </choice>
+</chapter>"#,
+        );
+
+        let source = p.chapter.unwrap().source.unwrap();
+        let at = |line, col| Loc { line, col };
+        // line, prompt, await, and the jump out of the option, which belongs to the choice
+        assert_eq!(source.exprs, vec![at(2, 5), at(3, 5), at(4, 34), at(3, 5)]);
+        assert_eq!(source.children[0], vec![at(2, 11), at(2, 18), at(2, 28)]);
+        assert_eq!(source.children[1], vec![at(4, 23)]);
+        assert!(source.children[2].is_empty());
     }
 }
@@ -0,0 +1,674 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use ir::ast::Story;
+use roxmltree::{Node, NodeType};
+
+use crate::story::parse_xml;
+use crate::{is_generated, languages, parse_story, ChapterParser, ErrorKind, ParseError};
+
+// translators get a gettext .po file rather than our xml. each entry is a line, keyed by its id
+// (which every line needs its own of first, see ids.rs), with the markup swapped for numbered
+// placeholders so there's nothing to break:
+//
+// #: intro.xml:3:5
+// msgctxt "intro.3"
+// msgid "The <1>universe</1> is silent."
+// msgstr "L'<1>univers</1> est silencieux."
+//
+// <1>..</1> wraps text in whichever element was there, and <2/> stands for an empty one,
+// like a <pause/> or a <?play?>. importing puts the base language's markup back, so every
+// placeholder has to be kept, exactly once (they can move around, and nest differently).
+// fuzzy and untranslated entries are left out, so those lines fall back to the base language.
+
+// an element or instruction in a line, which a placeholder stands for
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Tag {
+    // e.g. <pause ms="300"/> or <?play sfx=blip?>, as <n/>
+    Empty(String),
+    // e.g. <s4> and </s4>, as <n> and </n>
+    Pair(String, String),
+}
+
+// a line in the base language, with its placeholders
+pub(crate) struct Entry {
+    pub(crate) id: String,
+    pub(crate) chapter: String,
+    // where it is, e.g. intro.xml:3:5
+    reference: String,
+    pub(crate) text: String,
+    pub(crate) tags: Vec<Tag>,
+}
+
+impl Entry {
+    // imported translations remember this as base="..", so we can tell when the line changes
+    pub(crate) fn hash(&self) -> String {
+        format!("{:08x}", ir::bundle::fnv1a(self.text.as_bytes()) as u32)
+    }
+}
+
+// writes a .po of every line in the story, with lang's translations of them if it has any
+pub fn export_po(src_dir: &Path, lang: &str) -> Result<String, Vec<ParseError>> {
+    let (base, _) = languages(src_dir)?;
+    let base_dir = src_dir.join(&base);
+    let story = parse_story(&base_dir)?;
+    let entries = entries(&base_dir, &story);
+
+    let generated: Vec<ParseError> = entries
+        .iter()
+        .filter(|entry| is_generated(&entry.id))
+        .map(|entry| {
+            ParseError::in_file(
+                &entry.reference,
+                ErrorKind::GeneratedLineId(entry.id.clone()),
+            )
+        })
+        .collect();
+    if !generated.is_empty() {
+        return Err(generated);
+    }
+
+    let path = src_dir.join(lang).join("strings.xml");
+    let source = std::fs::read_to_string(&path).unwrap_or_default();
+    let file = path.display().to_string();
+    let doc = if source.is_empty() {
+        None
+    } else {
+        Some(parse_xml(&file, &source)?)
+    };
+    let translated: BTreeMap<&str, Node> = doc
+        .iter()
+        .flat_map(|doc| doc.root_element().children())
+        .filter(|n| n.is_element())
+        .filter_map(|n| Some((n.attribute("id")?, n)))
+        .collect();
+
+    let mut po = String::new();
+    writeln!(
+        po,
+        "# {} translation of void's dialogue, from {}.",
+        lang, base
+    )
+    .unwrap();
+    writeln!(
+        po,
+        "# <1>..</1> and <2/> stand for markup, and every one has to be kept."
+    )
+    .unwrap();
+    writeln!(po, "msgid \"\"").unwrap();
+    writeln!(po, "msgstr \"\"").unwrap();
+    writeln!(po, "\"Content-Type: text/plain; charset=UTF-8\\n\"").unwrap();
+    writeln!(po, "\"Language: {}\\n\"", lang).unwrap();
+
+    for entry in &entries {
+        let translation = translated.get(entry.id.as_str()).map(|node| {
+            let mut numbering = Numbering::matching(&entry.tags);
+            numbering.replace(&source, *node)
+        });
+
+        writeln!(po).unwrap();
+        writeln!(po, "#: {}", entry.reference).unwrap();
+        writeln!(po, "msgctxt {}", quote(&entry.id)).unwrap();
+        writeln!(po, "msgid {}", quote(&entry.text)).unwrap();
+        writeln!(po, "msgstr {}", quote(&translation.unwrap_or_default())).unwrap();
+    }
+
+    Ok(po)
+}
+
+// rebuilds src_dir/<lang>/strings.xml from a translated .po, returning how many lines it has.
+// nothing is written unless every entry's placeholders check out
+pub fn import_po(src_dir: &Path, lang: &str, po_path: &Path) -> Result<usize, Vec<ParseError>> {
+    let (base, _) = languages(src_dir)?;
+    let base_dir = src_dir.join(&base);
+    let story = parse_story(&base_dir)?;
+    let entries = entries(&base_dir, &story);
+
+    let file = po_path.display().to_string();
+    let source = std::fs::read_to_string(po_path)
+        .map_err(|e| vec![ParseError::in_file(&file, ErrorKind::Io(e.to_string()))])?;
+    let mut errors = vec![];
+    let mut lines = BTreeMap::new();
+
+    for po in parse_po(&file, &source)? {
+        if po.id.is_empty() || po.translation.is_empty() || po.fuzzy {
+            continue; // the header, or not translated yet
+        }
+        let error = |line, kind| ParseError::new(&file, &source, line, 1, kind);
+
+        let id = match &po.ctxt {
+            Some(id) => id,
+            None => {
+                let kind =
+                    ErrorKind::InvalidPo("entries need a msgctxt with a line id".to_string());
+                errors.push(error(po.line, kind));
+                continue;
+            }
+        };
+        let entry = match entries.iter().find(|e| e.id == *id) {
+            Some(entry) => entry,
+            None => {
+                errors.push(error(po.ctxt_line, ErrorKind::UndefinedLineId(id.clone())));
+                continue;
+            }
+        };
+        if is_generated(id) {
+            errors.push(error(po.ctxt_line, ErrorKind::GeneratedLineId(id.clone())));
+            continue;
+        }
+
+        match restore(&po.translation, &entry.tags) {
+            Ok(xml) => {
+                // stray <s and &s would only be caught when the game's built otherwise
+                let line = format!("<line>{}</line>", xml);
+                if let Err(e) = roxmltree::Document::parse(&line) {
+                    errors.push(error(po.line, ErrorKind::Xml(e.to_string())));
+                }
+                lines.insert(id.clone(), xml);
+            }
+            Err(message) => {
+                let kind = ErrorKind::Placeholder {
+                    id: id.clone(),
+                    message,
+                };
+                errors.push(error(po.line, kind));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // in story order, so it reads like the chapters do
+    let mut xml = String::from("<strings>\n");
+    for entry in &entries {
+        if let Some(line) = lines.get(&entry.id) {
+            let id = entry.id.replace('&', "&amp;").replace('"', "&quot;");
+            let hash = entry.hash();
+            writeln!(
+                xml,
+                "    <line id=\"{}\" base=\"{}\">{}</line>",
+                id, hash, line
+            )
+            .unwrap();
+        }
+    }
+    xml.push_str("</strings>\n");
+
+    let out_path = src_dir.join(lang).join("strings.xml");
+    let write = || {
+        std::fs::create_dir_all(src_dir.join(lang))?;
+        std::fs::write(&out_path, xml)
+    };
+    write().map_err(|e| {
+        vec![ParseError::in_file(
+            &out_path.display().to_string(),
+            ErrorKind::Io(e.to_string()),
+        )]
+    })?;
+
+    Ok(lines.len())
+}
+
+// every line in the story, in order
+pub(crate) fn entries(dir: &Path, story: &Story) -> Vec<Entry> {
+    let mut entries = vec![];
+
+    for chapter in &story.chapters {
+        // parse_story always makes source maps, they're only left out of compiled stories
+        let file = match &chapter.source {
+            Some(source) => &source.file,
+            None => continue,
+        };
+        let source = match std::fs::read_to_string(dir.join(file)) {
+            Ok(source) => source,
+            Err(_) => continue,
+        };
+
+        // the chapter's already been checked, this is just to find its lines again
+        let parser =
+            ChapterParser::with_types(&chapter.id, file, &source, BTreeMap::new(), &story.world);
+        let doc = match &parser.doc {
+            Some(doc) => doc.clone(),
+            None => continue,
+        };
+
+        for (id, range) in &parser.line_ranges {
+            let node = match doc.descendants().find(|n| n.range() == *range) {
+                Some(node) => node,
+                None => continue,
+            };
+            let pos = doc.text_pos_at(range.start);
+            let mut numbering = Numbering::new();
+            let text = numbering.replace(&source, node);
+
+            entries.push(Entry {
+                id: id.clone(),
+                chapter: chapter.id.clone(),
+                reference: format!("{}:{}:{}", file, pos.row, pos.col),
+                text,
+                tags: numbering.tags,
+            });
+        }
+    }
+
+    entries
+}
+
+// gives each tag in a line a number
+pub(crate) struct Numbering<'t> {
+    pub(crate) tags: Vec<Tag>,
+    // when numbering a translation, the base line's tags and which are taken
+    base: Option<(&'t [Tag], Vec<bool>)>,
+}
+
+impl<'t> Numbering<'t> {
+    pub(crate) fn new() -> Self {
+        Self {
+            tags: vec![],
+            base: None,
+        }
+    }
+
+    // a translation's tags get the number of the same markup in the base line,
+    // or are left as xml if the base line doesn't have it
+    fn matching(base: &'t [Tag]) -> Self {
+        Self {
+            tags: vec![],
+            base: Some((base, vec![false; base.len()])),
+        }
+    }
+
+    fn number(&mut self, tag: Tag) -> Option<usize> {
+        match &mut self.base {
+            None => {
+                self.tags.push(tag);
+                Some(self.tags.len())
+            }
+            Some((base, used)) => {
+                let i = (0..base.len()).find(|i| !used[*i] && base[*i] == tag)?;
+                used[i] = true;
+                Some(i + 1)
+            }
+        }
+    }
+
+    // a node's contents, with its markup replaced by placeholders
+    pub(crate) fn replace(&mut self, source: &str, node: Node) -> String {
+        let mut out = String::new();
+
+        for child in node.children() {
+            let raw = &source[child.range()];
+            match child.node_type() {
+                NodeType::Text => out.push_str(raw),
+                NodeType::Element if child.has_children() => {
+                    let inner = child.first_child().unwrap().range().start
+                        ..child.last_child().unwrap().range().end;
+                    let open = &source[child.range().start..inner.start];
+                    let close = &source[inner.end..child.range().end];
+
+                    // numbered before its contents, so placeholders count up through the line
+                    let n = self.number(Tag::Pair(open.to_string(), close.to_string()));
+                    let contents = self.replace(source, child);
+                    match n {
+                        Some(n) => write!(out, "<{}>{}</{}>", n, contents, n).unwrap(),
+                        None => write!(out, "{}{}{}", open, contents, close).unwrap(),
+                    }
+                }
+                NodeType::Element | NodeType::PI => {
+                    match self.number(Tag::Empty(raw.to_string())) {
+                        Some(n) => write!(out, "<{}/>", n).unwrap(),
+                        None => out.push_str(raw),
+                    }
+                }
+                _ => {} // comments
+            }
+        }
+
+        out
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Placeholder {
+    Open(usize),
+    Close(usize),
+    Empty(usize),
+}
+
+// <1>, </1> or <1/> at the start of s, and how long it is
+fn placeholder(s: &str) -> Option<(Placeholder, usize)> {
+    let rest = s.strip_prefix('<')?;
+    let (close, rest) = match rest.strip_prefix('/') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let n = rest[..digits].parse().ok()?;
+    let rest = &rest[digits..];
+    let used = s.len() - rest.len();
+
+    match (close, rest.starts_with("/>"), rest.starts_with('>')) {
+        (false, true, _) => Some((Placeholder::Empty(n), used + 2)),
+        (false, _, true) => Some((Placeholder::Open(n), used + 1)),
+        (true, _, true) => Some((Placeholder::Close(n), used + 1)),
+        _ => None,
+    }
+}
+
+// puts the base line's markup back where its placeholders are
+fn restore(text: &str, tags: &[Tag]) -> Result<String, String> {
+    let mut out = String::new();
+    let mut used = vec![false; tags.len()];
+    let mut open = vec![];
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let (placeholder, len) = match placeholder(rest) {
+            Some(found) => found,
+            None => {
+                // markup the translator added themselves
+                out.push('<');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        rest = &rest[len..];
+
+        let n = match placeholder {
+            Placeholder::Open(n) | Placeholder::Close(n) | Placeholder::Empty(n) => n,
+        };
+        let tag = match tags.get(n.wrapping_sub(1)) {
+            Some(tag) => tag,
+            None => return Err(format!("<{}> isn't one of the line's placeholders", n)),
+        };
+
+        match (placeholder, tag) {
+            (Placeholder::Close(_), Tag::Pair(_, close)) => {
+                if open.pop() != Some(n) {
+                    return Err(format!(
+                        "</{}> doesn't close the last placeholder opened",
+                        n
+                    ));
+                }
+                out.push_str(close);
+                continue;
+            }
+            (Placeholder::Open(_), Tag::Pair(tag, _)) => {
+                open.push(n);
+                out.push_str(tag);
+            }
+            (Placeholder::Empty(_), Tag::Empty(tag)) => out.push_str(tag),
+            (_, Tag::Pair(..)) => {
+                return Err(format!("<{}> should wrap text, as <{0}>..</{0}>", n))
+            }
+            (_, Tag::Empty(_)) => return Err(format!("<{}> should be on its own, as <{0}/>", n)),
+        }
+
+        if std::mem::replace(&mut used[n - 1], true) {
+            return Err(format!("<{}> is used more than once", n));
+        }
+    }
+    out.push_str(rest);
+
+    if let Some(n) = open.pop() {
+        return Err(format!("<{}> is never closed", n));
+    }
+    match used.iter().position(|used| !used) {
+        Some(i) => Err(format!("<{}> is missing", i + 1)),
+        None => Ok(out),
+    }
+}
+
+fn quote(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
+
+fn unquote(s: &str) -> Option<String> {
+    let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        out.push(match c {
+            '\\' => match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                c => c,
+            },
+            c => c,
+        });
+    }
+    Some(out)
+}
+
+#[derive(Debug, Default)]
+struct PoEntry {
+    ctxt: Option<String>,
+    id: String,
+    translation: String,
+    fuzzy: bool,
+    // where the msgctxt and msgstr are, for errors
+    ctxt_line: u32,
+    line: u32,
+}
+
+// just enough of the format for what export_po writes and editors write back.
+// plurals aren't supported, lines don't have them
+fn parse_po(file: &str, source: &str) -> Result<Vec<PoEntry>, Vec<ParseError>> {
+    let mut entries = vec![];
+    let mut entry = PoEntry::default();
+    // which of the entry's strings a continuation line adds to
+    let mut field: Option<&str> = None;
+    let mut errors = vec![];
+
+    for (n, line) in source.lines().enumerate() {
+        let n = n as u32 + 1;
+        let line = line.trim();
+        let error = |message: &str| {
+            let kind = ErrorKind::InvalidPo(message.to_string());
+            ParseError::new(file, source, n, 1, kind)
+        };
+
+        // comments and flags are about the entry after them, so the one before is done
+        if line.starts_with('#') && field == Some("msgstr") {
+            entries.push(std::mem::take(&mut entry));
+            field = None;
+        }
+        if line.starts_with("#,") && line.contains("fuzzy") {
+            entry.fuzzy = true;
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (keyword, value) = match line.find(' ') {
+            Some(i) if !line.starts_with('"') => (&line[..i], line[i..].trim()),
+            _ => ("", line),
+        };
+        let value = match unquote(value) {
+            Some(value) => value,
+            None => {
+                errors.push(error("expected a quoted string"));
+                continue;
+            }
+        };
+
+        // a new msgctxt or msgid after a msgstr starts the next entry
+        if (keyword == "msgctxt" || keyword == "msgid") && field == Some("msgstr") {
+            entries.push(std::mem::take(&mut entry));
+        }
+
+        match keyword {
+            "msgctxt" => {
+                entry.ctxt = Some(value);
+                entry.ctxt_line = n;
+            }
+            "msgid" => entry.id = value,
+            "msgstr" => {
+                entry.translation = value;
+                entry.line = n;
+            }
+            "" => match field {
+                Some("msgctxt") => entry.ctxt.get_or_insert_with(String::new).push_str(&value),
+                Some("msgid") => entry.id.push_str(&value),
+                Some(_) => entry.translation.push_str(&value),
+                None => errors.push(error("a string has to follow msgctxt, msgid or msgstr")),
+            },
+            _ => {
+                errors.push(error(&format!("unsupported keyword {}", keyword)));
+                continue;
+            }
+        }
+        if !keyword.is_empty() {
+            field = Some(keyword);
+        }
+    }
+    if field.is_some() {
+        entries.push(entry);
+    }
+
+    if errors.is_empty() {
+        Ok(entries)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export_po, import_po, parse_po, restore, Tag};
+    use crate::ErrorKind;
+
+    #[test]
+    fn placeholders() {
+        let tags = vec![
+            Tag::Pair("<s4>".to_string(), "</s4>".to_string()),
+            Tag::Empty("<pause ms=\"300\"/>".to_string()),
+        ];
+        assert_eq!(
+            restore("<2/>L'<1>univers</1>", &tags).unwrap(),
+            "<pause ms=\"300\"/>L'<s4>univers</s4>"
+        );
+
+        let error = |text| restore(text, &tags).unwrap_err();
+        assert_eq!(error("<1>univers</1>"), "<2> is missing");
+        assert_eq!(
+            error("<1>un</1><1>ivers</1><2/>"),
+            "<1> is used more than once"
+        );
+        assert_eq!(error("<1>univers<2/>"), "<1> is never closed");
+        assert_eq!(error("<3/>"), "<3> isn't one of the line's placeholders");
+        assert_eq!(error("<2>univers</2>"), "<2> should be on its own, as <2/>");
+    }
+
+    #[test]
+    fn fuzzy() {
+        let po = r#"
+msgctxt "one"
+msgid "One."
+msgstr "Un."
+
+#: intro.xml:3:5
+#, fuzzy
+msgctxt "two"
+msgid "Two."
+msgstr "Deux ?"
+
+msgctxt "three"
+msgid "Three."
+msgstr "Trois."
+"#;
+        let entries = parse_po("fr.po", po).unwrap();
+        let fuzzy: Vec<_> = entries.iter().map(|e| (e.id.as_str(), e.fuzzy)).collect();
+        assert_eq!(
+            fuzzy,
+            vec![("One.", false), ("Two.", true), ("Three.", false)]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let src = std::env::temp_dir().join("void-po");
+        let _ = std::fs::remove_dir_all(&src);
+        std::fs::create_dir_all(src.join("en")).unwrap();
+        let files = [
+            (
+                "story.xml",
+                r#"<story voices="../voices.xml"><chapter id="intro" src="intro.xml"/></story>"#,
+            ),
+            (
+                "intro.xml",
+                r#"<chapter voice="universe">
+    <line id="silent">The <s4>universe</s4> is <pause ms="300"/>"silent".</line>
+    <line id="dots"><s0>...</s0></line>
+</chapter>"#,
+            ),
+        ];
+        for (file, contents) in &files {
+            std::fs::write(src.join("en").join(file), contents).unwrap();
+        }
+        std::fs::write(
+            src.join("voices.xml"),
+            r#"<voices><voice name="universe"/></voices>"#,
+        )
+        .unwrap();
+
+        let po = export_po(&src, "fr").unwrap();
+        assert!(po.contains(
+            "#: intro.xml:2:5\n\
+             msgctxt \"silent\"\n\
+             msgid \"The <1>universe</1> is <2/>\\\"silent\\\".\"\n\
+             msgstr \"\"\n"
+        ));
+
+        // a translator's been at it, and left the second line for later
+        let po = po.replace(
+            "\\\"silent\\\".\"\nmsgstr \"\"",
+            "\\\"silent\\\".\"\nmsgstr \"L'<1>univers</1> est <2/>« silencieux ».\"",
+        );
+        let po_path = src.join("fr.po");
+        std::fs::write(&po_path, &po).unwrap();
+
+        assert_eq!(import_po(&src, "fr", &po_path).unwrap(), 1);
+        let strings = std::fs::read_to_string(src.join("fr").join("strings.xml")).unwrap();
+        let hash = ir::bundle::fnv1a(b"The <1>universe</1> is <2/>\"silent\".") as u32;
+        assert_eq!(
+            strings,
+            format!(
+                "<strings>\n    \
+                 <line id=\"silent\" base=\"{:08x}\">\
+                 L'<s4>univers</s4> est <pause ms=\"300\"/>« silencieux ».</line>\n\
+                 </strings>\n",
+                hash
+            )
+        );
+        let (_, tables) = crate::parse_all(&src).unwrap();
+        assert_eq!(tables[0].lines.len(), 1);
+
+        // exporting again keeps the translation
+        assert!(export_po(&src, "fr")
+            .unwrap()
+            .contains("msgstr \"L'<1>univers</1> est <2/>« silencieux ».\""));
+
+        std::fs::write(&po_path, po.replace("est <2/>", "est ")).unwrap();
+        let errors = import_po(&src, "fr", &po_path).unwrap_err();
+        assert_eq!(
+            errors[0].kind,
+            ErrorKind::Placeholder {
+                id: "silent".to_string(),
+                message: "<2> is missing".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use ir::ast::{LineChild, Story, StringTable};
+
+// a fake translation, for finding text that won't fit or won't render before real ones exist.
+// "The universe is silent." becomes "[Ŧĥé ũñîṽéŕšé îš šîĺéñţ.······]": every letter is accented,
+// it's padded out about as much as other languages tend to run longer than english,
+// and the brackets show when a line's been cut off. markup and instructions are left alone.
+
+pub const PSEUDO: &str = "pseudo";
+
+pub fn pseudo_localize(story: &Story) -> StringTable {
+    let lines = story
+        .lines()
+        .map(|(id, content)| (id.to_string(), pseudo_line(content)))
+        .collect::<BTreeMap<_, _>>();
+
+    StringTable {
+        lang: PSEUDO.to_string(),
+        lines,
+    }
+}
+
+fn pseudo_line(content: &[LineChild]) -> Vec<LineChild> {
+    let mut content = content.to_vec();
+    let mut spans = content.iter_mut().filter_map(|child| match child {
+        LineChild::Span(span) => Some(span),
+        _ => None,
+    });
+
+    let first = match spans.next() {
+        Some(span) => span,
+        None => return content,
+    };
+    let mut len = first.text.chars().count();
+    first.text = format!("[{}", accent(&first.text));
+
+    let mut last = first;
+    for span in spans {
+        len += span.text.chars().count();
+        span.text = accent(&span.text);
+        last = span;
+    }
+
+    let padding = "·".repeat(len.div_ceil(4));
+    last.text = format!("{}{}]", last.text, padding);
+    content
+}
+
+fn accent(text: &str) -> String {
+    const LOWER: [char; 26] = [
+        'á', 'ƀ', 'ç', 'ď', 'é', 'ƒ', 'ĝ', 'ĥ', 'î', 'ĵ', 'ķ', 'ĺ', 'ɱ', 'ñ', 'ö', 'þ', 'ǫ', 'ŕ',
+        'š', 'ţ', 'ũ', 'ṽ', 'ŵ', 'ẋ', 'ý', 'ž',
+    ];
+    const UPPER: [char; 26] = [
+        'Å', 'Ɓ', 'Ç', 'Ď', 'É', 'Ƒ', 'Ĝ', 'Ĥ', 'Î', 'Ĵ', 'Ķ', 'Ĺ', 'Ṁ', 'Ñ', 'Ö', 'Þ', 'Ǫ', 'Ŕ',
+        'Š', 'Ŧ', 'Ũ', 'Ṽ', 'Ŵ', 'Ẋ', 'Ý', 'Ž',
+    ];
+
+    text.chars()
+        .map(|c| match c {
+            'a'..='z' => LOWER[c as usize - 'a' as usize],
+            'A'..='Z' => UPPER[c as usize - 'A' as usize],
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pseudo_line;
+    use ir::ast::{Instruction, LineChild, Span, TextProperties};
+
+    fn span(text: &str, speed: u32) -> LineChild {
+        LineChild::Span(Span {
+            text: text.to_string(),
+            properties: TextProperties {
+                speed,
+                voice: "universe".to_string(),
+                effects: vec![],
+            },
+        })
+    }
+
+    #[test]
+    fn pseudo_text() {
+        let stop = LineChild::Instruction(Instruction::Stop);
+        let line = vec![
+            span("The ", 3),
+            span("universe", 4),
+            stop.clone(),
+            span(" is silent.", 3),
+            LineChild::Pause(30),
+        ];
+
+        assert_eq!(
+            pseudo_line(&line),
+            vec![
+                span("[Ŧĥé ", 3),
+                span("ũñîṽéŕšé", 4),
+                stop,
+                span(" îš šîĺéñţ.······]", 3),
+                LineChild::Pause(30),
+            ]
+        );
+        assert_eq!(pseudo_line(&[LineChild::Pause(30)]), [LineChild::Pause(30)]);
+    }
+}
@@ -0,0 +1,793 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use ir::ast::{ChExpr, Chapter, Story, Vocab, Voice, World};
+use roxmltree::Node;
+
+use crate::world::parse_world;
+use crate::{ChapterParser, ErrorKind, ParseError};
+
+// the base language's directory has a story.xml manifest, which lists its chapters in order:
+//
+// <story entry="intro">
+//     <chapter id="intro" src="intro.xml"/>
+//     <chapter id="hallway" src="hallway.xml"/>
+// </story>
+//
+// variables are shared by the whole story, so every chapter is scanned for
+// declarations before any of them are parsed.
+//
+// voices are shared with the translations, so the manifest points at them:
+//
+// <story entry="intro" voices="../voices.xml">
+//
+// <voices>
+//     <voice name="universe" color="#ffffff" blip="blip" speed="1.0"/>
+//     <voice name="stranger" color="#c0a0ff" blip="none" speed="0.5"/>
+// </voices>
+//
+// and if the story has <commands>, it points at the words they can use too:
+//
+// <story entry="intro" voices="../voices.xml" vocab="vocab.xml">
+//
+// <vocab>
+//     <verb name="take" synonyms="get, grab, pick up"/>
+//     <noun name="brass key"/>
+//     <noun name="north" synonyms="n"/>
+// </vocab>
+//
+// the player can call a noun by any of its words, so "key" could be the brass key,
+// and the game asks which one they meant if it could be more than one.
+//
+// a text adventure's rooms and items are in a world="world.xml" too (see world.rs).
+
+pub fn parse_story(dir: &Path) -> Result<Story, Vec<ParseError>> {
+    let path = dir.join("story.xml");
+    let file = path.display().to_string();
+    let manifest = std::fs::read_to_string(&path)
+        .map_err(|e| vec![ParseError::in_file(&file, ErrorKind::Io(e.to_string()))])?;
+    let doc = parse_xml(&file, &manifest)?;
+    let root = doc.root_element();
+    let mut errors = vec![];
+
+    if root.tag_name().name() != "story" {
+        let kind = unexpected("a <story>", root);
+        return Err(vec![ParseError::at(&file, &doc, root.range().start, kind)]);
+    }
+
+    // (id, path, source)
+    let mut sources: Vec<(String, String, String)> = vec![];
+    for node in root.children().filter(|n| n.is_element()) {
+        let error = |kind| ParseError::at(&file, &doc, node.range().start, kind);
+
+        if node.tag_name().name() != "chapter" {
+            errors.push(error(unexpected("a <chapter>", node)));
+            continue;
+        }
+
+        let (id, src) = match (node.attribute("id"), node.attribute("src")) {
+            (Some(id), Some(src)) => (id, src),
+            (None, _) => {
+                errors.push(error(missing("chapter", "id")));
+                continue;
+            }
+            (_, None) => {
+                errors.push(error(missing("chapter", "src")));
+                continue;
+            }
+        };
+
+        if sources.iter().any(|(other, _, _)| other == id) {
+            errors.push(error(ErrorKind::DuplicateChapter(id.to_string())));
+            continue;
+        }
+
+        let path = dir.join(src);
+        match std::fs::read_to_string(&path) {
+            Ok(source) => sources.push((id.to_string(), path.display().to_string(), source)),
+            Err(e) => errors.push(error(ErrorKind::Io(format!("{}: {}", src, e)))),
+        }
+    }
+
+    let entry = match root.attribute("entry") {
+        Some(entry) => entry.to_string(),
+        None => match sources.first() {
+            Some((id, _, _)) => id.clone(),
+            None => {
+                errors.push(ParseError::at(
+                    &file,
+                    &doc,
+                    root.range().start,
+                    ErrorKind::NoChapters,
+                ));
+                return Err(errors);
+            }
+        },
+    };
+
+    let voices = match root.attribute("voices") {
+        Some(path) => parse_voices(&dir.join(path)).unwrap_or_else(|mut e| {
+            errors.append(&mut e);
+            BTreeMap::new()
+        }),
+        None => BTreeMap::new(),
+    };
+
+    let vocab = match root.attribute("vocab") {
+        Some(path) => parse_vocab(&dir.join(path)).unwrap_or_else(|mut e| {
+            errors.append(&mut e);
+            Vocab::default()
+        }),
+        None => Vocab::default(),
+    };
+
+    let world = match root.attribute("world") {
+        Some(path) => parse_world(&dir.join(path)).unwrap_or_else(|mut e| {
+            errors.append(&mut e);
+            World::default()
+        }),
+        None => World::default(),
+    };
+
+    errors.extend(check_vars(&sources));
+
+    let mut types = BTreeMap::new();
+    for (_, _, source) in &sources {
+        types.extend(ChapterParser::declarations(source));
+    }
+
+    let mut chapters = vec![];
+    for (id, path, source) in &sources {
+        match ChapterParser::with_types(id, path, source, types.clone(), &world).into_result() {
+            Ok(mut chapter) => {
+                // errors want the whole path, but the game only needs to tell chapters apart
+                if let Some(source) = &mut chapter.source {
+                    if let Ok(file) = Path::new(path).strip_prefix(dir) {
+                        source.file = file.display().to_string();
+                    }
+                }
+                chapters.push(chapter);
+            }
+            Err(mut e) => errors.append(&mut e),
+        }
+    }
+
+    if !sources.iter().any(|(id, _, _)| *id == entry) {
+        let pos = root
+            .attribute_node("entry")
+            .map_or(root.range().start, |a| a.value_range().start);
+        errors.push(ParseError::at(
+            &file,
+            &doc,
+            pos,
+            ErrorKind::UndefinedChapter(entry.clone()),
+        ));
+    }
+
+    // chapters with errors of their own aren't checked, they'd only add noise
+    if errors.is_empty() {
+        for (_, path, source) in &sources {
+            errors.extend(check_references(path, source, &chapters, &voices, &vocab));
+        }
+        errors.extend(check_line_ids(&sources, &chapters));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(Story {
+        lang: dir
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().into_owned()),
+        entry,
+        chapters,
+        voices,
+        vocab,
+        world,
+    })
+}
+
+pub(crate) fn parse_xml<'a>(
+    file: &str,
+    source: &'a str,
+) -> Result<roxmltree::Document<'a>, Vec<ParseError>> {
+    roxmltree::Document::parse(source).map_err(|e| {
+        let pos = e.pos();
+        let kind = ErrorKind::Xml(e.to_string());
+        vec![ParseError::new(file, source, pos.row, pos.col, kind)]
+    })
+}
+
+pub(crate) fn unexpected(expected: &str, found: Node) -> ErrorKind {
+    ErrorKind::UnexpectedElement {
+        expected: expected.to_string(),
+        found: found.tag_name().name().to_string(),
+    }
+}
+
+pub(crate) fn missing(element: &str, attr: &str) -> ErrorKind {
+    ErrorKind::MissingAttribute {
+        element: element.to_string(),
+        attr: attr.to_string(),
+    }
+}
+
+fn parse_voices(path: &Path) -> Result<BTreeMap<String, Voice>, Vec<ParseError>> {
+    let file = path.display().to_string();
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| vec![ParseError::in_file(&file, ErrorKind::Io(e.to_string()))])?;
+    let doc = parse_xml(&file, &source)?;
+    let mut voices = BTreeMap::new();
+    let mut errors = vec![];
+
+    for node in doc.root_element().children().filter(|n| n.is_element()) {
+        let error = |pos, kind| ParseError::at(&file, &doc, pos, kind);
+        let attr_pos = |attr| {
+            node.attribute_node(attr)
+                .map_or(node.range().start, |a| a.value_range().start)
+        };
+
+        if node.tag_name().name() != "voice" {
+            errors.push(error(node.range().start, unexpected("a <voice>", node)));
+            continue;
+        }
+
+        let name = match node.attribute("name") {
+            Some(name) => name,
+            None => {
+                errors.push(error(node.range().start, missing("voice", "name")));
+                continue;
+            }
+        };
+
+        let color = node.attribute("color").map_or(Some([255; 4]), parse_color);
+        let speed = node
+            .attribute("speed")
+            .map_or(Some(1.0), |s| s.parse().ok().filter(|speed| *speed > 0.0));
+
+        let (color, speed) = match (color, speed) {
+            (Some(color), Some(speed)) => (color, speed),
+            (None, _) => {
+                let s = node.attribute("color").unwrap().to_string();
+                errors.push(error(attr_pos("color"), ErrorKind::InvalidColor(s)));
+                continue;
+            }
+            (_, None) => {
+                let kind = ErrorKind::InvalidNumber {
+                    element: "voice".to_string(),
+                    attr: "speed".to_string(),
+                    value: node.attribute("speed").unwrap().to_string(),
+                };
+                errors.push(error(attr_pos("speed"), kind));
+                continue;
+            }
+        };
+
+        let voice = Voice {
+            color,
+            blip: match node.attribute("blip") {
+                Some("none") => None,
+                Some(blip) => Some(blip.to_string()),
+                None => Some("blip".to_string()),
+            },
+            speed,
+        };
+
+        if voices.insert(name.to_string(), voice).is_some() {
+            let kind = ErrorKind::DuplicateVoice(name.to_string());
+            errors.push(error(node.range().start, kind));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(voices)
+    } else {
+        Err(errors)
+    }
+}
+
+fn parse_vocab(path: &Path) -> Result<Vocab, Vec<ParseError>> {
+    let file = path.display().to_string();
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| vec![ParseError::in_file(&file, ErrorKind::Io(e.to_string()))])?;
+    let doc = parse_xml(&file, &source)?;
+    let mut vocab = Vocab::default();
+    let mut errors = vec![];
+    // every way of saying a verb, so two verbs can't be said the same way
+    let mut verbs = BTreeSet::new();
+
+    for node in doc.root_element().children().filter(|n| n.is_element()) {
+        let error = |kind| ParseError::at(&file, &doc, node.range().start, kind);
+        let kind = node.tag_name().name();
+
+        let words = match kind {
+            "verb" => &mut vocab.verbs,
+            "noun" => &mut vocab.nouns,
+            _ => {
+                errors.push(error(unexpected("a <verb> or <noun>", node)));
+                continue;
+            }
+        };
+        let name = match node.attribute("name") {
+            Some(name) => normalize(name),
+            None => {
+                errors.push(error(missing(kind, "name")));
+                continue;
+            }
+        };
+        let synonyms: Vec<String> = node
+            .attribute("synonyms")
+            .unwrap_or("")
+            .split(',')
+            .map(normalize)
+            .filter(|synonym| !synonym.is_empty())
+            .collect();
+
+        if kind == "verb" {
+            for word in std::iter::once(&name).chain(&synonyms) {
+                if !verbs.insert(word.clone()) {
+                    errors.push(error(ErrorKind::DuplicateWord(word.clone())));
+                }
+            }
+        }
+        if words.insert(name.clone(), synonyms).is_some() && kind == "noun" {
+            errors.push(error(ErrorKind::DuplicateWord(name)));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(vocab)
+    } else {
+        Err(errors)
+    }
+}
+
+// lowercase, with single spaces between words
+pub(crate) fn normalize(words: &str) -> String {
+    words
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// #rrggbb or #rrggbbaa
+fn parse_color(s: &str) -> Option<[u8; 4]> {
+    let hex = s
+        .strip_prefix('#')
+        .filter(|hex| (hex.len() == 6 || hex.len() == 8) && hex.is_ascii())?;
+
+    let mut color = [255; 4];
+    for (i, c) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *c = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(color)
+}
+
+// gotos to other chapters, voices and commands' words can only be checked
+// once the whole story is parsed.
+// the ir doesn't know where anything came from, so this looks at the chapter's xml again.
+// translations use it for their voices, with no chapters
+pub(crate) fn check_references(
+    file: &str,
+    source: &str,
+    chapters: &[Chapter],
+    voices: &BTreeMap<String, Voice>,
+    vocab: &Vocab,
+) -> Vec<ParseError> {
+    let doc = match roxmltree::Document::parse(source) {
+        Ok(doc) => doc,
+        Err(_) => return vec![],
+    };
+    let mut errors = vec![];
+
+    for node in doc.descendants().filter(|n| n.is_element()) {
+        let mut error = |attr: &str, kind| {
+            let pos = node
+                .attribute_node(attr)
+                .map_or(node.range().start, |a| a.value_range().start);
+            errors.push(ParseError::at(file, &doc, pos, kind));
+        };
+
+        // every span's voice comes from one of these
+        let voice = match node.tag_name().name() {
+            "chapter" | "line" => node.attribute("voice").map(|v| ("voice", v)),
+            "voice" => node.attribute("name").map(|v| ("name", v)),
+            _ => None,
+        };
+        if let Some((attr, voice)) = voice {
+            if !voices.contains_key(voice) {
+                error(attr, ErrorKind::UndefinedVoice(voice.to_string()));
+            }
+        }
+
+        if node.tag_name().name() == "on" {
+            if let Some(verb) = node.attribute("verb") {
+                if !vocab.verbs.contains_key(&normalize(verb)) {
+                    error("verb", ErrorKind::UndefinedVerb(verb.to_string()));
+                }
+            }
+            if let Some(noun) = node.attribute("noun") {
+                if !vocab.nouns.contains_key(&normalize(noun)) {
+                    error("noun", ErrorKind::UndefinedNoun(noun.to_string()));
+                }
+            }
+        }
+
+        // a call has to say which label, so the chapter's own parser already complained
+        // if it didn't
+        if let "goto" | "call" = node.tag_name().name() {
+            if let Some(id) = node.attribute("chapter") {
+                match chapters.iter().find(|c| c.id == id) {
+                    None => error("chapter", ErrorKind::UndefinedChapter(id.to_string())),
+                    Some(target) => {
+                        if let Some(label) = node.attribute("label") {
+                            if !target.labels.contains_key(label) {
+                                let label = format!("{}#{}", id, label);
+                                error("label", ErrorKind::UndefinedLabel(label));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+// chapters make sure their own line ids are unique, but two chapters could still share one
+fn check_line_ids(sources: &[(String, String, String)], chapters: &[Chapter]) -> Vec<ParseError> {
+    let mut seen = BTreeSet::new();
+    let mut errors = vec![];
+
+    for ((_, file, source), chapter) in sources.iter().zip(chapters) {
+        for (idx, expr) in chapter.content.iter().enumerate() {
+            let ids = match expr {
+                ChExpr::Line { id, .. } => vec![id],
+                ChExpr::Prompt { options } => options.iter().map(|o| &o.id).collect(),
+                _ => continue,
+            };
+
+            for id in ids {
+                if !seen.insert(id) {
+                    // chapters always have a source map here, release builds strip it later
+                    let loc = chapter
+                        .source
+                        .as_ref()
+                        .and_then(|s| s.exprs.get(idx).copied())
+                        .unwrap_or_default();
+                    let kind = ErrorKind::DuplicateLineId(id.clone());
+                    errors.push(ParseError::new(file, source, loc.line, loc.col, kind));
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+// chapters make sure their own variables are declared once, but they all share them,
+// so two chapters can't both declare one. every declaration is reported, with where the others are
+fn check_vars(sources: &[(String, String, String)]) -> Vec<ParseError> {
+    let mut declared: BTreeMap<String, Vec<(&str, ParseError)>> = BTreeMap::new();
+
+    for (id, file, source) in sources {
+        // anything wrong with the xml is the chapter's to report
+        let doc = match roxmltree::Document::parse(source) {
+            Ok(doc) => doc,
+            Err(_) => continue,
+        };
+        for node in doc.descendants().filter(|n| n.tag_name().name() == "var") {
+            if let Some(name) = node.attribute_node("name") {
+                let pos = name.value_range().start;
+                // this becomes more specific once we know where the others are
+                let kind = ErrorKind::DuplicateVar(name.value().to_string());
+                let error = ParseError::at(file, &doc, pos, kind);
+                declared
+                    .entry(name.value().to_string())
+                    .or_default()
+                    .push((id.as_str(), error));
+            }
+        }
+    }
+
+    let mut errors = vec![];
+    for (name, declarations) in declared {
+        if declarations.iter().all(|(id, _)| *id == declarations[0].0) {
+            continue;
+        }
+        let locs: Vec<String> = declarations
+            .iter()
+            .map(|(_, e)| format!("{}:{}:{}", e.file, e.line, e.col))
+            .collect();
+        for (i, (_, mut error)) in declarations.into_iter().enumerate() {
+            let mut others = locs.clone();
+            others.remove(i);
+            error.kind = ErrorKind::VarDeclaredElsewhere {
+                name: name.clone(),
+                others: others.join(", "),
+            };
+            errors.push(error);
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_story;
+    use crate::ErrorKind;
+    use ir::ast::{ChExpr, CommandHandler, Expr, Type};
+    use std::path::PathBuf;
+
+    fn write_story(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("void-story-{}", name));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            std::fs::write(dir.join(file), contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn cross_chapter() {
+        let dir = write_story(
+            "cross-chapter",
+            &[
+                (
+                    "story.xml",
+                    r#"<story entry="intro" voices="voices.xml">
+                        <chapter id="intro" src="intro.xml"/>
+                        <chapter id="hallway" src="hallway.xml"/>
+                    </story>"#,
+                ),
+                (
+                    "voices.xml",
+                    r##"<voices>
+                        <voice name="universe" color="#ffffff80" blip="none" speed="0.5"/>
+                    </voices>"##,
+                ),
+                (
+                    "intro.xml",
+                    r#"<chapter voice="universe">
+                        <set flag="awake"/>
+                        <goto chapter="hallway" label="door"/>
+                    </chapter>"#,
+                ),
+                (
+                    "hallway.xml",
+                    r#"<chapter voice="universe">
+                        <label name="door"/>
+                        <if cond="awake"><line>A door.</line></if>
+                    </chapter>"#,
+                ),
+            ],
+        );
+
+        let story = parse_story(&dir).unwrap();
+        assert_eq!(story.entry, "intro");
+        assert_eq!(story.voices["universe"].color, [255, 255, 255, 128]);
+        assert_eq!(story.voices["universe"].blip, None);
+        assert_eq!(story.chapter_idx("hallway"), Some(1));
+        assert_eq!(
+            story.chapters[0].content[1],
+            ChExpr::GotoChapter {
+                chapter: "hallway".to_string(),
+                label: Some("door".to_string())
+            }
+        );
+
+        // hallway.xml only knows what `awake` is because intro.xml sets it
+        if let ChExpr::Branch { cond, .. } = &story.chapters[1].content[0] {
+            assert_eq!(*cond, Expr::Var("awake".to_string(), Type::Bool));
+        } else {
+            panic!("expected a branch");
+        }
+    }
+
+    #[test]
+    fn goto_undefined_chapter() {
+        let dir = write_story(
+            "undefined-chapter",
+            &[
+                (
+                    "story.xml",
+                    r#"<story><chapter id="intro" src="intro.xml"/></story>"#,
+                ),
+                (
+                    "intro.xml",
+                    r#"<chapter voice="universe"><goto chapter="nowhere"/></chapter>"#,
+                ),
+            ],
+        );
+
+        let errors = parse_story(&dir).unwrap_err();
+        let kinds: Vec<_> = errors.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                // there's no voices.xml either
+                ErrorKind::UndefinedVoice("universe".to_string()),
+                ErrorKind::UndefinedChapter("nowhere".to_string())
+            ]
+        );
+        assert_eq!((errors[1].line, errors[1].col), (1, 42));
+    }
+
+    #[test]
+    fn call_undefined_label() {
+        let dir = write_story(
+            "call-undefined-label",
+            &[
+                (
+                    "story.xml",
+                    r#"<story voices="voices.xml">
+                        <chapter id="intro" src="intro.xml"/>
+                        <chapter id="interludes" src="interludes.xml"/>
+                    </story>"#,
+                ),
+                ("voices.xml", r#"<voices><voice name="universe"/></voices>"#),
+                (
+                    "intro.xml",
+                    r#"<chapter voice="universe">
+                        <call chapter="interludes" label="hum"/>
+                        <call chapter="interludes" label="drone"/>
+                        <call chapter="outro" label="hum"/>
+                    </chapter>"#,
+                ),
+                (
+                    "interludes.xml",
+                    r#"<chapter voice="universe">
+                        <label name="hum"/><line>The void hums.</line><return/>
+                    </chapter>"#,
+                ),
+            ],
+        );
+
+        let errors = parse_story(&dir).unwrap_err();
+        let kinds: Vec<_> = errors.into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ErrorKind::UndefinedLabel("interludes#drone".to_string()),
+                ErrorKind::UndefinedChapter("outro".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn var_in_two_chapters() {
+        let dir = write_story(
+            "var-in-two-chapters",
+            &[
+                (
+                    "story.xml",
+                    r#"<story voices="voices.xml">
+                        <chapter id="intro" src="intro.xml"/>
+                        <chapter id="end" src="end.xml"/>
+                    </story>"#,
+                ),
+                ("voices.xml", r#"<voices><voice name="universe"/></voices>"#),
+                (
+                    "intro.xml",
+                    r#"<chapter voice="universe"><var name="trust" value="0"/></chapter>"#,
+                ),
+                (
+                    "end.xml",
+                    r#"<chapter voice="universe">
+                        <var name="trust" value="3"/>
+                        <var name="mood" value="'calm'"/>
+                    </chapter>"#,
+                ),
+            ],
+        );
+
+        let errors = parse_story(&dir).unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.kind.clone(), e.line, e.col))
+            .collect();
+        let elsewhere = |file: &str, line, col| ErrorKind::VarDeclaredElsewhere {
+            name: "trust".to_string(),
+            others: format!("{}:{}:{}", dir.join(file).display(), line, col),
+        };
+        assert_eq!(
+            found,
+            vec![
+                (elsewhere("end.xml", 2, 36), 1, 38),
+                (elsewhere("intro.xml", 1, 38), 2, 36),
+            ]
+        );
+    }
+
+    #[test]
+    fn commands() {
+        let files = |chapter| {
+            [
+                (
+                    "story.xml",
+                    r#"<story voices="voices.xml" vocab="vocab.xml">
+                        <chapter id="intro" src="intro.xml"/>
+                    </story>"#,
+                ),
+                ("voices.xml", r#"<voices><voice name="universe"/></voices>"#),
+                (
+                    "vocab.xml",
+                    r#"<vocab>
+                        <verb name="Take" synonyms="get,  pick   up"/>
+                        <noun name="brass key"/>
+                    </vocab>"#,
+                ),
+                ("intro.xml", chapter),
+            ]
+        };
+
+        let dir = write_story(
+            "commands",
+            &files(
+                r#"<chapter voice="universe">
+                    <commands><on verb="take" noun="Brass Key" label="take"/></commands>
+                    <label name="take"/>
+                </chapter>"#,
+            ),
+        );
+        let story = parse_story(&dir).unwrap();
+        assert_eq!(
+            story.vocab.verbs["take"],
+            vec!["get".to_string(), "pick up".to_string()]
+        );
+        assert_eq!(
+            story.chapters[0].content[0],
+            ChExpr::Command {
+                handlers: vec![CommandHandler {
+                    verb: "take".to_string(),
+                    noun: Some("brass key".to_string()),
+                    target: 1
+                }]
+            }
+        );
+
+        let dir = write_story(
+            "unknown-label",
+            &files(
+                r#"<chapter voice="universe">
+                    <commands><on verb="eat" noun="key" label="nowhere"/></commands>
+                </chapter>"#,
+            ),
+        );
+        let kinds: Vec<_> = parse_story(&dir)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![ErrorKind::UndefinedLabel("nowhere".to_string())]
+        );
+
+        let dir = write_story(
+            "undefined-words",
+            &files(
+                r#"<chapter voice="universe">
+                    <commands><on verb="eat" noun="key" label="eat"/></commands>
+                    <label name="eat"/>
+                </chapter>"#,
+            ),
+        );
+        let kinds: Vec<_> = parse_story(&dir)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ErrorKind::UndefinedVerb("eat".to_string()),
+                ErrorKind::UndefinedNoun("key".to_string())
+            ]
+        );
+    }
+}
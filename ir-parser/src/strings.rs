@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use ir::ast::{ChExpr, Expr, LineChild, Story, StringTable, Type};
+use roxmltree::NodeType;
+
+use crate::story::check_references;
+use crate::{is_generated, ChapterParser, ErrorKind, ParseError};
+
+// every language but the base one is a strings.xml, translating the base language's lines by id:
+//
+// <strings>
+//     <line id="intro.3">L'<s4>univers</s4> est silencieux.</line>
+//     <line id="who-are-you">Qui es-tu ?</line>
+// </strings>
+//
+// a line's id is the one it has in its chapter. lines without one can't be translated until
+// voidc ids gives them one (see ids.rs). lines can be marked up like any other, and start out
+// in the voice of the line they translate. anything missing is shown in the base language.
+// voidc import also gives each line a base="..", the hash of the line it translated,
+// so check_translations can tell when that's changed. {expressions} can use any variable
+// the story reads.
+
+pub fn parse_strings(dir: &Path, story: &Story) -> Result<StringTable, Vec<ParseError>> {
+    let path = dir.join("strings.xml");
+    let file = path.display().to_string();
+    let source = std::fs::read_to_string(&path)
+        .map_err(|e| vec![ParseError::in_file(&file, ErrorKind::Io(e.to_string()))])?;
+
+    // translations take the voice of the line they replace
+    let mut voices = BTreeMap::new();
+    for chapter in &story.chapters {
+        for (id, content) in chapter.lines() {
+            let voice = content.iter().find_map(|child| match child {
+                LineChild::Span(span) => Some(&span.properties.voice),
+                _ => None,
+            });
+            voices.insert(id, voice.unwrap_or(&chapter.voice));
+        }
+    }
+
+    let mut parser = ChapterParser::new(&file, &source);
+    parser.types = story_types(story);
+    parser.world = story.world.clone();
+    let doc = match parser.doc.clone() {
+        Some(doc) => doc,
+        None => return Err(parser.errors),
+    };
+    let root = doc.root_element();
+    let mut lines = BTreeMap::new();
+
+    if root.tag_name().name() != "strings" {
+        let kind = ErrorKind::UnexpectedElement {
+            expected: "a <strings>".to_string(),
+            found: root.tag_name().name().to_string(),
+        };
+        parser.error(root, kind);
+    }
+
+    for node in root.children() {
+        match node.node_type() {
+            NodeType::Element if node.tag_name().name() == "line" => {}
+            NodeType::Element => {
+                let kind = ErrorKind::UnexpectedElement {
+                    expected: "a <line>".to_string(),
+                    found: node.tag_name().name().to_string(),
+                };
+                parser.error(node, kind);
+                continue;
+            }
+            NodeType::Text if !ChapterParser::text_is_whitespace(&node) => {
+                parser.error(node, ErrorKind::UnexpectedText);
+                continue;
+            }
+            _ => continue,
+        }
+
+        let id = match parser.require(node, "id") {
+            Some(id) => id,
+            None => continue,
+        };
+        if is_generated(id) {
+            parser.error(node, ErrorKind::GeneratedLineId(id.to_string()));
+            continue;
+        }
+        match voices.get(id) {
+            Some(voice) => parser.voice = voice.to_string(),
+            // the line's gone, or its id was changed. that shouldn't break the build,
+            // so it's left out here and check_translations reports it
+            None => continue,
+        }
+
+        // a line is parsed onto the stack, then popped back off
+        parser.parse_line(node);
+        if let Some(ChExpr::Line { content, .. }) = parser.expr_stack.as_mut().unwrap().pop() {
+            lines.insert(id.to_string(), content);
+        }
+    }
+
+    let mut errors = parser.errors;
+    if errors.is_empty() {
+        errors.extend(check_references(
+            &file,
+            &source,
+            &[],
+            &story.voices,
+            &story.vocab,
+        ));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(StringTable {
+        lang: dir
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().into_owned()),
+        lines,
+    })
+}
+
+// the story only keeps variables' types where they're used, so they're collected from there
+fn story_types(story: &Story) -> BTreeMap<String, Type> {
+    fn visit(expr: &Expr, types: &mut BTreeMap<String, Type>) {
+        match expr {
+            Expr::Lit(_) | Expr::Query(_) => {}
+            Expr::Var(name, ty) => {
+                types.insert(name.clone(), *ty);
+            }
+            Expr::Unary(_, expr) => visit(expr, types),
+            Expr::Binary(_, lhs, rhs) => {
+                visit(lhs, types);
+                visit(rhs, types);
+            }
+        }
+    }
+
+    let mut types = BTreeMap::new();
+    for chapter in &story.chapters {
+        for (name, value) in &chapter.vars {
+            types.insert(name.clone(), value.ty());
+        }
+        for expr in &chapter.content {
+            match expr {
+                ChExpr::Set { value: expr, .. } | ChExpr::Branch { cond: expr, .. } => {
+                    visit(expr, &mut types)
+                }
+                ChExpr::Input { var, .. } => {
+                    types.insert(var.clone(), Type::Str);
+                }
+                _ => {}
+            }
+        }
+        for (_, content) in chapter.lines() {
+            for child in content {
+                if let LineChild::Interpolation { expr, .. } = child {
+                    visit(expr, &mut types);
+                }
+            }
+        }
+    }
+    types
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_strings;
+    use crate::ErrorKind;
+    use ir::ast::{ChExpr, Chapter, LineChild, Span, Story, TextProperties, Vocab, World};
+    use std::collections::BTreeMap;
+
+    fn span(text: &str, voice: &str) -> LineChild {
+        LineChild::Span(Span {
+            text: text.to_string(),
+            properties: TextProperties {
+                speed: 3,
+                voice: voice.to_string(),
+                effects: vec![],
+            },
+        })
+    }
+
+    #[test]
+    fn translations() {
+        let story = Story {
+            lang: "en".to_string(),
+            entry: "intro".to_string(),
+            chapters: vec![Chapter {
+                id: "intro".to_string(),
+                voice: "universe".to_string(),
+                content: vec![ChExpr::Line {
+                    id: "hi".to_string(),
+                    content: vec![span("Hi.", "stranger")],
+                }],
+                labels: BTreeMap::new(),
+                vars: BTreeMap::new(),
+                source: None,
+            }],
+            voices: BTreeMap::new(),
+            vocab: Vocab::default(),
+            world: World::default(),
+        };
+
+        let dir = std::env::temp_dir().join("void-strings").join("fr");
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |source: &str| std::fs::write(dir.join("strings.xml"), source).unwrap();
+
+        write(r#"<strings><line id="hi">Salut.</line></strings>"#);
+        let table = parse_strings(&dir, &story).unwrap();
+        assert_eq!(table.lang, "fr");
+        // still said by whoever said it in english
+        assert_eq!(table.lines["hi"], vec![span("Salut.", "stranger")]);
+
+        // lines the story doesn't have are skipped
+        write(r#"<strings><line id="bye">Au revoir.</line></strings>"#);
+        assert!(parse_strings(&dir, &story).unwrap().lines.is_empty());
+
+        write(r#"<strings><line>?</line></strings>"#);
+        let errors = parse_strings(&dir, &story).unwrap_err();
+        let kinds: Vec<_> = errors.into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![ErrorKind::MissingAttribute {
+                element: "line".to_string(),
+                attr: "id".to_string()
+            }]
+        );
+
+        // an unnamed line's id would move as soon as a line's added before it
+        write(r#"<strings><line id="intro~1">Salut.</line></strings>"#);
+        let errors = parse_strings(&dir, &story).unwrap_err();
+        assert_eq!(
+            errors[0].kind,
+            ErrorKind::GeneratedLineId("intro~1".to_string())
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// notices when anything in a directory is added, removed or modified.
+// it polls rather than using the os' file events, which is plenty for a handful of xml files
+pub struct Watcher {
+    dir: PathBuf,
+    times: BTreeMap<PathBuf, SystemTime>,
+}
+
+impl Watcher {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            times: modified_times(dir),
+        }
+    }
+
+    // whether anything changed since the last time this was called (or since new)
+    pub fn changed(&mut self) -> bool {
+        let times = modified_times(&self.dir);
+        let changed = times != self.times;
+        self.times = times;
+        changed
+    }
+}
+
+fn modified_times(dir: &Path) -> BTreeMap<PathBuf, SystemTime> {
+    let mut times = BTreeMap::new();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return times,
+    };
+
+    for path in entries.filter_map(Result::ok).map(|e| e.path()) {
+        if path.is_dir() {
+            times.extend(modified_times(&path));
+        } else if let Ok(time) = path.metadata().and_then(|m| m.modified()) {
+            times.insert(path, time);
+        }
+    }
+    times
+}
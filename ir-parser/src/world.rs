@@ -0,0 +1,196 @@
+use std::path::Path;
+
+use ir::ast::{Item, Room, World, INVENTORY};
+use roxmltree::Node;
+
+use crate::story::{missing, parse_xml, unexpected};
+use crate::{ErrorKind, ParseError};
+
+// a story with a world.xml is a text adventure, and this is its map:
+//
+// <world start="cell">
+//     <room id="cell">
+//         <exit dir="north" to="hallway"/>
+//     </room>
+//     <room id="hallway">
+//         <exit dir="south" to="cell"/>
+//     </room>
+//     <item id="brass key" in="cell"/>
+//     <item id="door" in="hallway" state="locked"/>
+// </world>
+//
+// an item's id is usually its noun in the vocab, so commands can tell which items are around.
+// items can start out in="inventory", and their state is whatever the story wants it to be.
+// dialogue asks about the world with here(), has(item), location(item), state(item) and
+// exit(dir), and changes it with <go room=..> or <go exit=..>, <move item=.. to=..>
+// and <state item=.. value=..>.
+
+// where the player is, as far as <move to=..> is concerned
+pub(crate) const HERE: &str = "here";
+
+pub fn parse_world(path: &Path) -> Result<World, Vec<ParseError>> {
+    let file = path.display().to_string();
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| vec![ParseError::in_file(&file, ErrorKind::Io(e.to_string()))])?;
+    let doc = parse_xml(&file, &source)?;
+    let root = doc.root_element();
+    let mut world = World::default();
+    let mut errors = vec![];
+
+    let error = |node: Node, attr: &str, kind| {
+        let pos = node
+            .attribute_node(attr)
+            .map_or(node.range().start, |a| a.value_range().start);
+        ParseError::at(&file, &doc, pos, kind)
+    };
+
+    if root.tag_name().name() != "world" {
+        return Err(vec![error(root, "", unexpected("a <world>", root))]);
+    }
+
+    // every room someone wants to go to, checked once they're all known
+    let mut targets = vec![];
+
+    for node in root.children().filter(|n| n.is_element()) {
+        match node.tag_name().name() {
+            "room" => {
+                let id = match node.attribute("id") {
+                    Some(id) => id,
+                    None => {
+                        errors.push(error(node, "", missing("room", "id")));
+                        continue;
+                    }
+                };
+                if id == INVENTORY || id == HERE {
+                    errors.push(error(node, "id", ErrorKind::ReservedRoom(id.to_string())));
+                    continue;
+                }
+
+                let mut room = Room::default();
+                for exit in node.children().filter(|n| n.is_element()) {
+                    if exit.tag_name().name() != "exit" {
+                        errors.push(error(exit, "", unexpected("an <exit>", exit)));
+                        continue;
+                    }
+                    match (exit.attribute("dir"), exit.attribute("to")) {
+                        (Some(dir), Some(to)) => {
+                            room.exits.insert(dir.to_string(), to.to_string());
+                            targets.push((exit, "to", to));
+                        }
+                        (None, _) => errors.push(error(exit, "", missing("exit", "dir"))),
+                        (_, None) => errors.push(error(exit, "", missing("exit", "to"))),
+                    }
+                }
+
+                if world.rooms.insert(id.to_string(), room).is_some() {
+                    errors.push(error(node, "id", ErrorKind::DuplicateRoom(id.to_string())));
+                }
+            }
+            "item" => {
+                let (id, location) = match (node.attribute("id"), node.attribute("in")) {
+                    (Some(id), Some(location)) => (id, location),
+                    (None, _) => {
+                        errors.push(error(node, "", missing("item", "id")));
+                        continue;
+                    }
+                    (_, None) => {
+                        errors.push(error(node, "", missing("item", "in")));
+                        continue;
+                    }
+                };
+                if location != INVENTORY {
+                    targets.push((node, "in", location));
+                }
+
+                let item = Item {
+                    location: location.to_string(),
+                    state: node.attribute("state").unwrap_or("").to_string(),
+                };
+                if world.items.insert(id.to_string(), item).is_some() {
+                    errors.push(error(node, "id", ErrorKind::DuplicateItem(id.to_string())));
+                }
+            }
+            _ => errors.push(error(node, "", unexpected("a <room> or <item>", node))),
+        }
+    }
+
+    match root.attribute("start") {
+        Some(start) => {
+            world.start = start.to_string();
+            targets.push((root, "start", start));
+        }
+        None if !world.rooms.is_empty() => errors.push(error(root, "", missing("world", "start"))),
+        None => {}
+    }
+
+    for (node, attr, room) in targets {
+        if !world.rooms.contains_key(room) {
+            errors.push(error(
+                node,
+                attr,
+                ErrorKind::UndefinedRoom(room.to_string()),
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(world)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_world;
+    use crate::ErrorKind;
+
+    fn parse(name: &str, source: &str) -> Result<ir::ast::World, Vec<ErrorKind>> {
+        let path = std::env::temp_dir().join(format!("void-world-{}.xml", name));
+        std::fs::write(&path, source).unwrap();
+        parse_world(&path).map_err(|errors| errors.into_iter().map(|e| e.kind).collect())
+    }
+
+    #[test]
+    fn world() {
+        let world = parse(
+            "ok",
+            r#"<world start="cell">
+                <room id="cell"><exit dir="north" to="hallway"/></room>
+                <room id="hallway"/>
+                <item id="brass key" in="cell"/>
+                <item id="lamp" in="inventory" state="off"/>
+            </world>"#,
+        )
+        .unwrap();
+        assert_eq!(world.start, "cell");
+        assert_eq!(world.rooms["cell"].exits["north"], "hallway");
+        assert_eq!(world.items["brass key"].location, "cell");
+        assert_eq!(world.items["brass key"].state, "");
+        assert_eq!(world.items["lamp"].state, "off");
+
+        let errors = parse(
+            "errors",
+            r#"<world>
+                <room id="here"/>
+                <room id="cell"><exit dir="north" to="nowhere"/></room>
+                <room id="cell"/>
+                <item id="key" in="attic"/>
+            </world>"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                ErrorKind::ReservedRoom("here".to_string()),
+                ErrorKind::DuplicateRoom("cell".to_string()),
+                ErrorKind::MissingAttribute {
+                    element: "world".to_string(),
+                    attr: "start".to_string()
+                },
+                ErrorKind::UndefinedRoom("nowhere".to_string()),
+                ErrorKind::UndefinedRoom("attic".to_string()),
+            ]
+        );
+    }
+}
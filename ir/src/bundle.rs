@@ -0,0 +1,160 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// compiled stories and string tables are wrapped up so the game can tell when they're stale:
+//
+// - magic bytes, b"VOID"
+// - the format version, as a little-endian u32
+// - a bincoded Header
+// - the bincoded Story or StringTable
+//
+// FORMAT_VERSION has to go up whenever ir::ast changes shape,
+// since bincode can't tell an old layout from a new one.
+
+pub const MAGIC: [u8; 4] = *b"VOID";
+pub const FORMAT_VERSION: u32 = 9;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Header {
+    pub version: u32,
+    // whatever compiled it, e.g. "ir-parser 0.1.0"
+    pub compiler: String,
+    // fnv-1a of the encoded body
+    pub hash: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LoadError {
+    // no magic bytes, so it's not compiled dialogue (or it's from before there was a header)
+    NotAStory,
+    // compiled by something older or newer than this build
+    Version { found: u32, compiler: String },
+    // the body doesn't match its hash
+    Corrupt,
+    // it claims to be the right version, but doesn't decode
+    Invalid(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::NotAStory => write!(f, "not compiled dialogue"),
+            LoadError::Version { found, compiler } => write!(
+                f,
+                "compiled by {} as version {}, but this build reads version {}",
+                compiler, found, FORMAT_VERSION
+            ),
+            LoadError::Corrupt => write!(f, "the contents don't match their hash"),
+            LoadError::Invalid(e) => write!(f, "couldn't decode it: {}", e),
+        }?;
+        write!(
+            f,
+            " - recompile your dialogue (cargo build, or voidc compile)"
+        )
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+pub fn encode<T: Serialize>(value: &T, compiler: &str) -> Vec<u8> {
+    let body = bincode::serialize(value).unwrap();
+    let header = Header {
+        version: FORMAT_VERSION,
+        compiler: compiler.to_string(),
+        hash: fnv1a(&body),
+    };
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend(bincode::serialize(&header).unwrap());
+    bytes.extend(body);
+    bytes
+}
+
+pub fn read_header(bytes: &[u8]) -> Result<Header, LoadError> {
+    header_and_body(bytes).map(|(header, _)| header)
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, LoadError> {
+    let (header, body) = header_and_body(bytes)?;
+    if fnv1a(body) != header.hash {
+        return Err(LoadError::Corrupt);
+    }
+
+    bincode::deserialize(body).map_err(|e| LoadError::Invalid(e.to_string()))
+}
+
+fn header_and_body(bytes: &[u8]) -> Result<(Header, &[u8]), LoadError> {
+    if bytes.len() < 8 || bytes[..4] != MAGIC {
+        return Err(LoadError::NotAStory);
+    }
+
+    let mut version = [0; 4];
+    version.copy_from_slice(&bytes[4..8]);
+    let version = u32::from_le_bytes(version);
+
+    // the header itself could change between versions, so only trust it if it's ours.
+    // older versions would be migrated here, but there aren't any yet
+    let mut rest = &bytes[8..];
+    match bincode::deserialize_from::<_, Header>(&mut rest) {
+        Ok(header) if version == FORMAT_VERSION => Ok((header, rest)),
+        Ok(Header { compiler, .. }) => Err(LoadError::Version {
+            found: version,
+            compiler,
+        }),
+        Err(_) => Err(LoadError::Version {
+            found: version,
+            compiler: "an unknown compiler".to_string(),
+        }),
+    }
+}
+
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Story, Vocab, World};
+    use std::collections::BTreeMap;
+
+    fn story() -> Story {
+        Story {
+            lang: "en".to_string(),
+            entry: "intro".to_string(),
+            chapters: vec![],
+            voices: BTreeMap::new(),
+            vocab: Vocab::default(),
+            world: World::default(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = encode(&story(), "test");
+        assert_eq!(read_header(&bytes).unwrap().compiler, "test");
+        assert_eq!(decode(&bytes), Ok(story()));
+    }
+
+    #[test]
+    fn stale_files() {
+        // what compile_ir wrote before there was a header
+        let old = bincode::serialize(&story()).unwrap();
+        assert_eq!(decode::<Story>(&old), Err(LoadError::NotAStory));
+
+        let mut bytes = encode(&story(), "test");
+        bytes[4..8].copy_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(
+            decode::<Story>(&bytes),
+            Err(LoadError::Version { found: 0, .. })
+        ));
+
+        let mut bytes = encode(&story(), "test");
+        *bytes.last_mut().unwrap() ^= 1;
+        assert_eq!(decode::<Story>(&bytes), Err(LoadError::Corrupt));
+    }
+}
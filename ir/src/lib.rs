@@ -1,48 +1,398 @@
+pub mod bundle;
+
 pub mod ast {
     use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
 
     #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
     pub enum Instruction {
+        // start a song, replacing whatever was playing
         Play { sound: String },
+        // a one-off sound effect
+        Effect { sound: String },
+        Stop,
     }
 
-    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
     pub enum LineChild {
         Span(Span),
+        // a {value} in the text, typed out like a span once the line starts
+        Interpolation {
+            expr: Expr,
+            properties: TextProperties,
+        },
         Instruction(Instruction),
+        // stop typing for this many ticks
+        Pause(u64),
     }
 
     // enums
-    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
     pub enum Action {
         Await,
     }
 
-    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
     pub enum ChExpr {
         Action(Action),
         Instruction(Instruction),
-        Line { content: Vec<LineChild> },
+        // id keys the line in other languages' string tables
+        Line {
+            id: String,
+            content: Vec<LineChild>,
+        },
+        // do nothing for this many ticks
+        Wait(u64),
+        Prompt {
+            options: Vec<PromptOption>,
+        },
+        // continue from the expression at this index
+        Jump(usize),
+        // continue from the start of another chapter, or one of its labels
+        GotoChapter {
+            chapter: String,
+            label: Option<String>,
+        },
+        Set {
+            var: String,
+            value: Expr,
+        },
+        // carry on if the condition holds, otherwise continue from `otherwise`
+        Branch {
+            cond: Expr,
+            otherwise: usize,
+        },
+        // let the player type up to `max` characters, and store them in a string variable
+        Input {
+            var: String,
+            max: usize,
+        },
+        // move the player
+        Go(Destination),
+        // move an item to a room, the inventory, or wherever the player is
+        Move {
+            item: String,
+            to: Place,
+        },
+        SetState {
+            item: String,
+            state: String,
+        },
+        // let the player type a command like "take key", and continue from the first handler
+        // that understands it. if none do, carry on with whatever's next
+        Command {
+            handlers: Vec<CommandHandler>,
+        },
+        // continue from a label, in this chapter unless it says otherwise,
+        // and come back to whatever's next at the following Return
+        Call {
+            chapter: Option<String>,
+            label: String,
+        },
+        Return,
+        // continue from one of the variants, picked by the game's rng. like a prompt's options,
+        // each variant's content jumps past the rest when it's done
+        Random {
+            mode: RandomMode,
+            variants: Vec<Variant>,
+        },
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub enum RandomMode {
+        // any variant, by weight. the same one can come up twice in a row
+        Weighted,
+        // every variant once, in a random order, before any of them come up again
+        Shuffle,
+        // every variant in turn, in order. weights don't matter
+        Cycle,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub struct Variant {
+        pub weight: u32,
+        pub target: usize,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub enum Destination {
+        Room(String),
+        // through one of the current room's exits, e.g. "north". nothing happens if it has none
+        Exit(String),
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub enum Place {
+        Room(String),
+        Inventory,
+        Here,
+    }
+
+    // a verb, and maybe a noun, from the story's vocab
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub struct CommandHandler {
+        pub verb: String,
+        pub noun: Option<String>,
+        pub target: usize,
+    }
+
+    // an option's text is shown to the player, and we jump to its target if they pick it
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub struct PromptOption {
+        // like a line's, for translating the text
+        pub id: String,
+        pub text: Vec<LineChild>,
+        pub target: usize,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub enum Value {
+        Bool(bool),
+        Int(i64),
+        Str(String),
+    }
+
+    impl Value {
+        pub fn ty(&self) -> Type {
+            match self {
+                Value::Bool(_) => Type::Bool,
+                Value::Int(_) => Type::Int,
+                Value::Str(_) => Type::Str,
+            }
+        }
+    }
+
+    // how a value reads when it's interpolated into a line
+    impl std::fmt::Display for Value {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Value::Bool(b) => write!(f, "{}", b),
+                Value::Int(n) => write!(f, "{}", n),
+                Value::Str(s) => write!(f, "{}", s),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+    pub enum Type {
+        Bool,
+        Int,
+        Str,
+    }
+
+    impl Type {
+        // what a variable holds before anything sets it
+        pub fn zero(self) -> Value {
+            match self {
+                Type::Bool => Value::Bool(false),
+                Type::Int => Value::Int(0),
+                Type::Str => Value::Str(String::new()),
+            }
+        }
+    }
+
+    // expressions are type checked by the compiler, so the runtime can trust them
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub enum Expr {
+        Lit(Value),
+        Var(String, Type),
+        Unary(UnOp, Box<Expr>),
+        Binary(BinOp, Box<Expr>, Box<Expr>),
+        Query(Query),
+    }
+
+    // what an expression can ask about the world, e.g. has('brass key')
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub enum Query {
+        // the room the player's in
+        Here,
+        // whether an item's in the inventory
+        Has(String),
+        // the room an item's in, or "inventory"
+        Location(String),
+        State(String),
+        // the room an exit from here leads to, or "" if there's no such exit
+        Exit(String),
+    }
+
+    impl Query {
+        pub fn ty(&self) -> Type {
+            match self {
+                Query::Has(_) => Type::Bool,
+                _ => Type::Str,
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+    pub enum UnOp {
+        Not,
+        Neg,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+    pub enum BinOp {
+        Add,
+        Sub,
+        Mul,
+        Div,
+        Rem,
+        Eq,
+        Ne,
+        Lt,
+        Le,
+        Gt,
+        Ge,
+        And,
+        Or,
     }
 
     // you know what it is 😎
     #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
     pub struct Chapter {
+        pub id: String,
         pub voice: String,
         pub content: Vec<ChExpr>,
+        // label name -> index into content
+        pub labels: BTreeMap<String, usize>,
+        // variables declared with <var>, and their initial values
+        pub vars: BTreeMap<String, Value>,
+        // where everything came from, if the chapter was compiled with source maps
+        pub source: Option<SourceMap>,
+    }
+
+    // the xml behind a chapter's expressions, for debugging. release builds leave it out
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+    pub struct SourceMap {
+        // relative to the language directory, e.g. "intro.xml"
+        pub file: String,
+        // one per expression in the chapter's content
+        pub exprs: Vec<Loc>,
+        // one per expression too, holding a line's children,
+        // or every child of a prompt's option texts in order
+        pub children: Vec<Vec<Loc>>,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+    pub struct Loc {
+        pub line: u32,
+        pub col: u32,
+    }
+
+    // every chapter, played in order unless a goto says otherwise.
+    // the text is in the base language, other languages are StringTables on top
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    pub struct Story {
+        // the base language, e.g. "en"
+        pub lang: String,
+        pub entry: String,
+        pub chapters: Vec<Chapter>,
+        pub voices: BTreeMap<String, Voice>,
+        pub vocab: Vocab,
+        pub world: World,
+    }
+
+    // how a voice's text looks and sounds
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+    pub struct Voice {
+        pub color: [u8; 4],
+        // the sound effect played for each character, if any
+        pub blip: Option<String>,
+        // multiplies how fast text is typed
+        pub speed: f32,
+    }
+
+    // the words commands are made of, each with any other ways of saying it,
+    // e.g. "take" -> ["get", "pick up"]. everything's lowercase
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+    pub struct Vocab {
+        pub verbs: BTreeMap<String, Vec<String>>,
+        pub nouns: BTreeMap<String, Vec<String>>,
+    }
+
+    // the map, and where everything starts out. the game keeps track of it from there
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+    pub struct World {
+        // the room the player starts in
+        pub start: String,
+        pub rooms: BTreeMap<String, Room>,
+        pub items: BTreeMap<String, Item>,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+    pub struct Room {
+        // direction -> room, e.g. "north" -> "hallway"
+        pub exits: BTreeMap<String, String>,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub struct Item {
+        // a room, or "inventory"
+        pub location: String,
+        // whatever the story wants, e.g. "locked". "" if it doesn't care
+        pub state: String,
+    }
+
+    // an item's location when the player has it
+    pub const INVENTORY: &str = "inventory";
+
+    impl Story {
+        pub fn chapter_idx(&self, id: &str) -> Option<usize> {
+            self.chapters.iter().position(|c| c.id == id)
+        }
+
+        // every line and option text in the story, by id
+        pub fn lines(&self) -> impl Iterator<Item = (&str, &[LineChild])> {
+            self.chapters.iter().flat_map(Chapter::lines)
+        }
+    }
+
+    impl Chapter {
+        pub fn lines(&self) -> impl Iterator<Item = (&str, &[LineChild])> {
+            self.content.iter().flat_map(|expr| match expr {
+                ChExpr::Line { id, content } => vec![(id.as_str(), content.as_slice())],
+                ChExpr::Prompt { options } => options
+                    .iter()
+                    .map(|o| (o.id.as_str(), o.text.as_slice()))
+                    .collect(),
+                _ => vec![],
+            })
+        }
+    }
+
+    // a language's translations of the story's lines, by id.
+    // lines that aren't here are shown in the base language
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+    pub struct StringTable {
+        pub lang: String,
+        pub lines: BTreeMap<String, Vec<LineChild>>,
     }
 
     #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
     pub enum Props {
         Speed(u32),
+        Voice(String),
+        Effect(Effect),
     }
 
-    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+    // how each glyph of a span moves or changes over time
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+    pub enum Effect {
+        Wiggle,
+        Wave,
+        Shake,
+        Rainbow,
+        FadeIn,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
     pub struct TextProperties {
         pub speed: u32,
+        pub voice: String,
+        pub effects: Vec<Effect>,
     }
 
-    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
     pub struct Span {
         pub text: String,
         pub properties: TextProperties,
@@ -0,0 +1,264 @@
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+use ir::ast::{Story, StringTable, World};
+use ir::bundle::{decode, read_header, LoadError};
+use ir_parser::{ChapterParser, CompileOptions, ParseError, Watcher};
+
+const USAGE: &str = "\
+voidc - compiles void's dialogue
+
+usage:
+    voidc compile [SRC] [OUT]   compile the story in SRC and its translations to OUT/<lang>/
+    voidc check [SRC]           report errors without writing anything
+    voidc dump <PATH>           pretty-print a chapter (.xml), a language directory, or a .bincode
+    voidc watch [SRC] [OUT]     compile again whenever something in SRC changes
+    voidc ids [SRC]             give every line without an id one, so it can be translated
+    voidc export <LANG> [SRC]   write a .po of every line to stdout, for translating into LANG
+    voidc import <LANG> <PO> [SRC]
+                                rebuild SRC/<LANG>/strings.xml from a translated .po
+    voidc translations [SRC] [--allow-missing]
+                                report missing, extra, stale and mismatched translations,
+                                failing if there are any (besides missing ones, if allowed)
+
+SRC defaults to dialogue-src, and OUT to game/dialogue.";
+
+const DEFAULT_SRC: &str = "dialogue-src";
+const DEFAULT_OUT: &str = "game/dialogue";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let src = |i: usize| PathBuf::from(args.get(i).copied().unwrap_or(DEFAULT_SRC));
+    let out = |i: usize| PathBuf::from(args.get(i).copied().unwrap_or(DEFAULT_OUT));
+
+    let ok = match args.as_slice() {
+        ["compile", ..] if args.len() <= 3 => compile(&src(1), &out(2)),
+        ["check", ..] if args.len() <= 2 => check(&src(1)),
+        ["dump", path] => dump(Path::new(path)),
+        ["watch", ..] if args.len() <= 3 => watch(&src(1), &out(2)),
+        ["ids", ..] if args.len() <= 2 => ids(&src(1)),
+        ["export", lang, ..] if args.len() <= 3 => export(lang, &src(2)),
+        ["import", lang, po, ..] if args.len() <= 4 => import(lang, Path::new(po), &src(3)),
+        ["translations", rest @ ..] if rest.len() <= 2 => {
+            let allow_missing = rest.contains(&"--allow-missing");
+            let src = rest.iter().find(|arg| !arg.starts_with("--"));
+            translations(
+                Path::new(src.copied().unwrap_or(DEFAULT_SRC)),
+                allow_missing,
+            )
+        }
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{}", USAGE);
+            true
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if !ok {
+        process::exit(1);
+    }
+}
+
+fn report(errors: &[ParseError]) {
+    for error in errors {
+        eprintln!("{}\n", error);
+    }
+    let s = if errors.len() == 1 { "" } else { "s" };
+    eprintln!("{} error{}", errors.len(), s);
+}
+
+fn compile(src: &Path, out: &Path) -> bool {
+    match ir_parser::compile_all(src, out, &CompileOptions::default()) {
+        Ok(langs) => {
+            println!("compiled {} to {}", langs.join(", "), out.display());
+            true
+        }
+        Err(errors) => {
+            report(&errors);
+            false
+        }
+    }
+}
+
+fn check(src: &Path) -> bool {
+    match ir_parser::parse_all(src) {
+        Ok((story, tables)) => {
+            let n = story.chapters.len();
+            let s = if n == 1 { "" } else { "s" };
+            let lines = story.lines().count();
+            println!("{}: {} chapter{}, {} lines, ok", story.lang, n, s, lines);
+            for table in tables {
+                let done = story
+                    .lines()
+                    .filter(|(id, _)| table.lines.contains_key(*id))
+                    .count();
+                println!("{}: {} of {} lines translated", table.lang, done, lines);
+            }
+            true
+        }
+        Err(errors) => {
+            report(&errors);
+            false
+        }
+    }
+}
+
+fn dump(path: &Path) -> bool {
+    if path.is_dir() {
+        // a translation needs the rest of the languages beside it
+        let parsed = match path.parent() {
+            Some(src) if !path.join("story.xml").is_file() => {
+                ir_parser::parse_all(src).map(|(_, tables)| {
+                    let table = tables.iter().find(|t| path.ends_with(&t.lang));
+                    table.map_or(String::new(), |table| format!("{:#?}", table))
+                })
+            }
+            _ => ir_parser::parse_story(path).map(|story| format!("{:#?}", story)),
+        };
+        return match parsed {
+            Ok(dump) => {
+                println!("{}", dump);
+                true
+            }
+            Err(errors) => {
+                report(&errors);
+                false
+            }
+        };
+    }
+
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("couldn't read {}: {}", path.display(), e);
+            return false;
+        }
+    };
+
+    if path.extension().and_then(|ext| ext.to_str()) == Some("bincode") {
+        let decoded: Result<String, LoadError> = match path.file_name() {
+            Some(name) if name == "strings.bincode" => {
+                decode::<StringTable>(&bytes).map(|table| format!("{:#?}", table))
+            }
+            _ => decode::<Story>(&bytes).map(|story| format!("{:#?}", story)),
+        };
+        return match (read_header(&bytes), decoded) {
+            (Ok(header), Ok(dump)) => {
+                println!("{:#?}\n{}", header, dump);
+                true
+            }
+            (_, Err(e)) | (Err(e), _) => {
+                eprintln!("{}: {}", path.display(), e);
+                false
+            }
+        };
+    }
+
+    let source = match String::from_utf8(bytes) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{} isn't utf-8: {}", path.display(), e);
+            return false;
+        }
+    };
+
+    // on its own, a chapter only knows about the variables it declares itself,
+    // and nothing about the story's world
+    let types = ChapterParser::declarations(&source);
+    let file = path.display().to_string();
+    let id = path
+        .file_stem()
+        .map_or(String::new(), |s| s.to_string_lossy().into_owned());
+    match ChapterParser::with_types(&id, &file, &source, types, &World::default()).into_result() {
+        Ok(chapter) => {
+            println!("{:#?}", chapter);
+            true
+        }
+        Err(errors) => {
+            report(&errors);
+            false
+        }
+    }
+}
+
+fn ids(src: &Path) -> bool {
+    match ir_parser::assign_ids(src) {
+        Ok(n) => {
+            let s = if n == 1 { "" } else { "s" };
+            println!("gave {} line{} an id", n, s);
+            true
+        }
+        Err(errors) => {
+            report(&errors);
+            false
+        }
+    }
+}
+
+fn export(lang: &str, src: &Path) -> bool {
+    match ir_parser::export_po(src, lang) {
+        Ok(po) => {
+            print!("{}", po);
+            true
+        }
+        Err(errors) => {
+            report(&errors);
+            false
+        }
+    }
+}
+
+fn import(lang: &str, po: &Path, src: &Path) -> bool {
+    match ir_parser::import_po(src, lang, po) {
+        Ok(n) => {
+            let path = src.join(lang).join("strings.xml");
+            println!("wrote {} translated lines to {}", n, path.display());
+            true
+        }
+        Err(errors) => {
+            report(&errors);
+            false
+        }
+    }
+}
+
+fn translations(src: &Path, allow_missing: bool) -> bool {
+    let reports = match ir_parser::check_translations(src) {
+        Ok(reports) => reports,
+        Err(errors) => {
+            report(&errors);
+            return false;
+        }
+    };
+
+    let mut ok = true;
+    for report in reports {
+        println!(
+            "{}: {} of {} lines translated",
+            report.lang, report.translated, report.lines
+        );
+        for issue in report.issues {
+            println!("    {}", issue);
+            ok &= allow_missing && issue.is_missing();
+        }
+    }
+    ok
+}
+
+fn watch(src: &Path, out: &Path) -> ! {
+    let mut watcher = Watcher::new(src);
+    compile(src, out);
+    loop {
+        println!("watching {} for changes...", src.display());
+        while !watcher.changed() {
+            std::thread::sleep(Duration::from_millis(500));
+        }
+        compile(src, out);
+    }
+}